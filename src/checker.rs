@@ -0,0 +1,681 @@
+use std::collections::HashMap;
+
+use crate::diagnostic::Diagnostic;
+use crate::interpreter::{array, binary, construct, element, literal_value, member, type_name, unary, Value};
+use crate::parse_tree::{NodeType, ParseTree};
+use crate::token::Token;
+
+// The most scalar values a variable of one type may hold, e.g. the elements of
+// an array, so a declaration like int32{2147483647} is not allocated at runtime
+const MAX_VALUES: usize = 1 << 20;
+
+// Semantic checks that need the whole parse tree:
+// every variable must be declared before it is used, a variable declared
+// without an initial value must be assigned on every path before it is read,
+// every call must name a defined function or struct with the right number of
+// arguments, every field accessed on a struct must exist, every type must
+// exist and hold at most MAX_VALUES values, an array literal initialising an
+// array variable must have its declared length, and every constant must have
+// a value known at compile time.
+pub fn check(program: &ParseTree) -> Vec<Diagnostic> {
+    let mut checker = Checker::new();
+    for definition in program.children() {
+        let name = definition.name();
+        let duplicate = match definition.node_type() {
+            NodeType::FunctionDefinition => {
+                checker.context = format!("function '{name}'");
+                if let [_, type_node, _] = definition.children() {
+                    checker.returns.insert(name.clone(), type_node);
+                }
+                let arity = definition.children()[0].children().len();
+                checker.functions.insert(name.clone(), arity).is_some() || checker.structs.contains_key(&name)
+            }
+            NodeType::StructDefinition => {
+                checker.context = format!("struct '{name}'");
+                checker.structs.insert(name.clone(), definition).is_some() || checker.functions.contains_key(&name)
+            }
+            _ => false,
+        };
+        if duplicate {
+            checker.error(definition, format!("'{name}' is defined more than once"));
+        }
+    }
+    for definition in program.children() {
+        if definition.node_type() == NodeType::StructDefinition {
+            checker.check_struct(definition);
+        }
+    }
+    // globals and constants are visible in every function, so declare them first
+    checker.scopes.push(HashMap::new());
+    for item in program.children() {
+        match item.node_type() {
+            NodeType::VariableDeclaration => checker.check_global(item),
+            NodeType::ConstantDeclaration => checker.check_constant(item),
+            _ => {}
+        }
+    }
+    for func in program.children() {
+        if func.node_type() == NodeType::FunctionDefinition {
+            checker.check_func(func);
+        }
+    }
+    checker.errors
+}
+
+
+#[derive(Debug, Clone)]
+struct Variable {
+    assigned: bool,  // definitely assigned at this point
+    constant: bool,
+    struct_type: Option<StructType>,  // when it holds a struct or an array of them
+}
+
+// A struct type, possibly as the element type of `dims` nested arrays, the
+// part of a type the checker needs to resolve field names
+#[derive(Debug, Clone, PartialEq)]
+struct StructType {
+    name: String,
+    dims: usize,
+}
+
+
+struct Checker<'a> {
+    functions: HashMap<String, usize>,       // function name -> number of parameters
+    returns: HashMap<String, &'a ParseTree>, // function name -> declared return type
+    structs: HashMap<String, &'a ParseTree>,
+    struct_sizes: HashMap<String, usize>,   // struct name -> values held by one instance
+    constants: HashMap<String, Value>,       // constant name -> value computed at compile time
+    scopes: Vec<HashMap<String, Variable>>,  // the outermost scope holds the globals
+    context: String,                         // where errors are reported, e.g. "function 'main'"
+    errors: Vec<Diagnostic>,
+}
+
+
+impl<'a> Checker<'a> {
+
+    fn new() -> Checker<'a> {
+        Checker {
+            functions: HashMap::new(),
+            returns: HashMap::new(),
+            structs: HashMap::new(),
+            struct_sizes: HashMap::new(),
+            constants: HashMap::new(),
+            scopes: vec![],
+            context: String::new(),
+            errors: vec![],
+        }
+    }
+
+    fn check_struct(&mut self, definition: &ParseTree) {
+        let name = definition.name();
+        self.context = format!("struct '{name}'");
+        let mut fields = vec![];
+        for field in definition.children() {
+            if fields.contains(&field.name()) {
+                self.error(field, format!("field '{}' is declared more than once", field.name()));
+            }
+            fields.push(field.name());
+            self.check_type(&field.children()[0]);
+        }
+        if self.contains_struct(definition, &name, &mut vec![]) {
+            self.error(definition, format!("struct '{name}' contains itself"));
+        }
+    }
+
+    // Does a field of `definition`, directly or through other structs, have the struct `target`?
+    fn contains_struct(&self, definition: &ParseTree, target: &str, seen: &mut Vec<String>) -> bool {
+        for field in definition.children() {
+            let mut field_type = &field.children()[0];
+            while field_type.node_type() == NodeType::ArrayType {
+                field_type = &field_type.children()[0];
+            }
+            let Token::ID(name) = field_type.token() else { continue };
+            if name == target {
+                return true;
+            }
+            if !seen.contains(name) {
+                seen.push(name.clone());
+                if let Some(inner) = self.structs.get(name) {
+                    if self.contains_struct(inner, target, seen) {
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    fn check_type(&mut self, type_node: &ParseTree) {
+        let mut element_type = type_node;
+        while element_type.node_type() == NodeType::ArrayType {
+            element_type = &element_type.children()[0];
+        }
+        if let Token::ID(name) = element_type.token() {
+            if !self.structs.contains_key(name) {
+                self.error(element_type, format!("unknown type '{name}'"));
+            }
+        }
+        let size = self.size(type_node);
+        if size > MAX_VALUES {
+            self.error(type_node, format!("type '{}' holds {size} values, at most {MAX_VALUES} are allowed", type_name(type_node)));
+        }
+    }
+
+    // The number of scalar values in a value of the type, saturating
+    fn size(&mut self, type_node: &ParseTree) -> usize {
+        match type_node.token() {
+            Token::LIT_INT32(length) => (*length as usize).saturating_mul(self.size(&type_node.children()[0])),
+            Token::ID(name) => {
+                if let Some(&size) = self.struct_sizes.get(name) {
+                    return size;
+                }
+                let Some(definition) = self.structs.get(name).copied() else {
+                    return 1;
+                };
+                // a struct containing itself is reported by check_struct, count it once
+                self.struct_sizes.insert(name.clone(), 1);
+                let size = definition.children().iter()
+                    .fold(0usize, |size, field| size.saturating_add(self.size(&field.children()[0])));
+                self.struct_sizes.insert(name.clone(), size);
+                size
+            }
+            _ => 1,
+        }
+    }
+
+    // An array literal must have the length of the array it initialises, at every level
+    fn check_initial_value(&mut self, type_node: &ParseTree, value: &ParseTree) {
+        let Token::LIT_INT32(length) = type_node.token() else {
+            return;
+        };
+        let length = *length as usize;
+        match value.node_type() {
+            NodeType::ArrayLiteral if value.children().len() != length => {
+                self.error(value, format!("expected an array of {length} element(s) for type '{}', but got {}",
+                                          type_name(type_node), value.children().len()));
+            }
+            NodeType::ArrayLiteral => {
+                for element in value.children() {
+                    self.check_initial_value(&type_node.children()[0], element);
+                }
+            }
+            NodeType::Literal => {
+                self.error(value, format!("expected an array of {length} element(s) for type '{}', but got a value of type '{}'",
+                                          type_name(type_node), literal_value(value.token()).type_name()));
+            }
+            _ => {}  // known only at runtime
+        }
+    }
+
+    fn check_global(&mut self, declaration: &ParseTree) {
+        let name = declaration.name();
+        self.context = format!("global '{name}'");
+        for child in declaration.children() {
+            if is_type(child) {
+                self.check_type(child);
+            } else {
+                self.check_expression(child);
+            }
+        }
+        if let [type_node, value] = declaration.children() {
+            self.check_initial_value(type_node, value);
+        }
+        // a global without an initial value starts out with the default value of its type
+        self.declare_global(declaration, false);
+    }
+
+    fn check_constant(&mut self, declaration: &ParseTree) {
+        let name = declaration.name();
+        self.context = format!("constant '{name}'");
+        self.check_type(&declaration.children()[0]);
+        if let Some(value) = self.evaluate_constant(&declaration.children()[1]) {
+            let expected = type_name(&declaration.children()[0]);
+            if expected != value.type_name() {
+                self.error(&declaration.children()[1], format!("constant is declared as '{expected}', but its value has type '{}'", value.type_name()));
+            }
+            self.constants.insert(name.clone(), value);
+        }
+        self.declare_global(declaration, true);
+    }
+
+    // Fold a constant expression to its value, reporting anything that is
+    // not known at compile time.
+    fn evaluate_constant(&mut self, expression: &ParseTree) -> Option<Value> {
+        match expression.node_type() {
+            NodeType::Literal => Some(literal_value(expression.token())),
+            NodeType::Identifier => {
+                let name = expression.name();
+                let value = self.constants.get(&name).cloned();
+                if value.is_none() {
+                    match self.lookup(&name) {
+                        Some(variable) if variable.constant => {}  // already reported
+                        Some(_) => self.error(expression, format!("'{name}' is a variable, not a constant")),
+                        None => self.error(expression, format!("use of undeclared constant '{name}'")),
+                    }
+                }
+                value
+            }
+            NodeType::BinaryExpression => {
+                let left = self.evaluate_constant(&expression.children()[0])?;
+                let right = self.evaluate_constant(&expression.children()[1])?;
+                self.fold(expression, binary(expression.token(), left, right))
+            }
+            NodeType::UnaryExpression => {
+                let operand = self.evaluate_constant(&expression.children()[0])?;
+                self.fold(expression, unary(expression.token(), operand))
+            }
+            NodeType::ArrayLiteral => {
+                let mut elements = vec![];
+                for child in expression.children() {
+                    elements.push(self.evaluate_constant(child)?);
+                }
+                self.fold(expression, array(elements))
+            }
+            NodeType::IndexExpression => {
+                let mut array = self.evaluate_constant(&expression.children()[0])?;
+                let index = self.evaluate_constant(&expression.children()[1])?;
+                let value = element(&mut array, index).cloned();
+                self.fold(expression, value)
+            }
+            NodeType::MemberExpression => {
+                let mut value = self.evaluate_constant(&expression.children()[0])?;
+                let value = member(&mut value, &expression.name()).cloned();
+                self.fold(expression, value)
+            }
+            NodeType::FunctionCall => match self.structs.get(&expression.name()).copied() {
+                Some(definition) => {
+                    let mut values = vec![];
+                    for child in expression.children() {
+                        values.push(self.evaluate_constant(child)?);
+                    }
+                    self.fold(expression, construct(definition, values))
+                }
+                None => {
+                    self.error(expression, format!("call to '{}' is not allowed in a constant expression", expression.name()));
+                    None
+                }
+            },
+            _ => None,
+        }
+    }
+
+    fn fold(&mut self, expression: &ParseTree, result: Result<Value, Diagnostic>) -> Option<Value> {
+        match result {
+            Ok(value) => Some(value),
+            Err(diagnostic) => {
+                self.error(expression, diagnostic.message);
+                None
+            }
+        }
+    }
+
+    fn check_func(&mut self, func: &ParseTree) {
+        self.context = format!("function '{}'", func.name());
+        self.scopes.push(HashMap::new());
+        for child in func.children() {
+            match child.node_type() {
+                NodeType::ParameterList => {
+                    for parameter in child.children() {
+                        self.check_type(&parameter.children()[0]);
+                        self.declare(parameter.name(), true, declared_struct(&parameter.children()[0]));
+                    }
+                }
+                NodeType::Type | NodeType::ArrayType => self.check_type(child),
+                NodeType::Block => self.check_statement(child),
+                _ => {}
+            }
+        }
+        self.scopes.pop();
+    }
+
+    fn check_statement(&mut self, statement: &ParseTree) {
+        match statement.node_type() {
+            NodeType::Block => {
+                self.scopes.push(HashMap::new());
+                for child in statement.children() {
+                    self.check_statement(child);
+                }
+                self.scopes.pop();
+            }
+            NodeType::VariableDeclaration => {
+                let declared_type = statement.children().iter().find(|child| is_type(child));
+                let initial_value = statement.children().iter().find(|child| !is_type(child));
+                if let Some(type_node) = declared_type {
+                    self.check_type(type_node);
+                }
+                if let Some(expression) = initial_value {
+                    self.check_expression(expression);
+                }
+                if let (Some(type_node), Some(expression)) = (declared_type, initial_value) {
+                    self.check_initial_value(type_node, expression);
+                }
+                // the elements of an array or the fields of a struct start out with
+                // the default value of their type
+                let aggregate = declared_type.is_some_and(|type_node| {
+                    matches!(type_node.token(), Token::LIT_INT32(_) | Token::ID(_))
+                });
+                let struct_type = match (declared_type, initial_value) {
+                    (Some(type_node), _) => declared_struct(type_node),
+                    (None, Some(expression)) => self.struct_of(expression),
+                    (None, None) => None,
+                };
+                self.declare(statement.name(), initial_value.is_some() || aggregate, struct_type);
+            }
+            NodeType::AssignmentStatement => {
+                for child in statement.children() {
+                    self.check_expression(child);
+                }
+                let name = statement.name();
+                self.check_selectors(statement);
+                let selected = statement.children().len() > 1;
+                match self.lookup(&name) {
+                    Some(variable) if variable.constant => self.error(statement, format!("cannot assign to constant '{name}'")),
+                    Some(variable) if selected && !variable.assigned => {
+                        self.error(statement, format!("variable '{name}' is used before it is definitely assigned"));
+                    }
+                    Some(variable) => variable.assigned = true,
+                    None => self.error(statement, format!("assignment to undeclared variable '{name}'")),
+                }
+            }
+            NodeType::IfStatement => {
+                self.check_expression(&statement.children()[0]);
+                // a variable is definitely assigned after the if when both branches assign it
+                let before = self.scopes.clone();
+                self.check_statement(&statement.children()[1]);
+                let after_then = std::mem::replace(&mut self.scopes, before);
+                if let Some(otherwise) = statement.children().get(2) {
+                    self.check_statement(otherwise);
+                }
+                for (scope, then_scope) in self.scopes.iter_mut().zip(after_then) {
+                    for (name, variable) in scope.iter_mut() {
+                        variable.assigned &= then_scope[name].assigned;
+                    }
+                }
+            }
+            NodeType::WhileStatement => {
+                self.check_expression(&statement.children()[0]);
+                // the body may run zero times, so nothing it assigns is definite afterwards
+                let before = self.scopes.clone();
+                self.check_statement(&statement.children()[1]);
+                self.scopes = before;
+            }
+            NodeType::ReturnStatement | NodeType::PrintStatement => {
+                for child in statement.children() {
+                    self.check_expression(child);
+                }
+            }
+            NodeType::FunctionCall => self.check_expression(statement),
+            _ => {}
+        }
+    }
+
+    fn check_expression(&mut self, expression: &ParseTree) {
+        match expression.node_type() {
+            NodeType::Identifier => {
+                let name = expression.name();
+                match self.lookup(&name).map(|variable| variable.assigned) {
+                    Some(true) => {}
+                    Some(false) => self.error(expression, format!("variable '{name}' is used before it is definitely assigned")),
+                    None => self.error(expression, format!("use of undeclared variable '{name}'")),
+                }
+            }
+            NodeType::FunctionCall => {
+                let name = expression.name();
+                let count = expression.children().len();
+                if let Some(definition) = self.structs.get(&name) {
+                    let fields = definition.children().len();
+                    if fields != count {
+                        self.error(expression, format!("struct '{name}' has {fields} field(s), but got {count} value(s)"));
+                    }
+                } else {
+                    match self.functions.get(&name) {
+                        Some(&arity) if arity != count => {
+                            self.error(expression, format!("function '{name}' expects {arity} argument(s), but got {count}"));
+                        }
+                        Some(_) => {}
+                        None => self.error(expression, format!("call to undefined function '{name}'")),
+                    }
+                }
+                for child in expression.children() {
+                    self.check_expression(child);
+                }
+            }
+            NodeType::MemberExpression => {
+                let object = &expression.children()[0];
+                self.check_expression(object);
+                if let Some(struct_type) = self.struct_of(object) {
+                    if let Err(message) = self.field(&struct_type, &expression.name()) {
+                        self.error(expression, message);
+                    }
+                }
+            }
+            _ => {
+                for child in expression.children() {
+                    self.check_expression(child);
+                }
+            }
+        }
+    }
+
+    // The fields assigned to, like `p.x` in `p.x = 1;` or `ps{0}.x`, must exist
+    fn check_selectors(&mut self, statement: &ParseTree) {
+        let [selectors @ .., _] = statement.children() else {
+            return;
+        };
+        let mut current = self.lookup(&statement.name()).and_then(|variable| variable.struct_type.clone());
+        for selector in selectors {
+            let Some(mut struct_type) = current else {
+                return;
+            };
+            current = match selector.node_type() {
+                NodeType::Index if struct_type.dims > 0 => {
+                    struct_type.dims -= 1;
+                    Some(struct_type)
+                }
+                NodeType::Member => match self.field(&struct_type, &selector.name()) {
+                    Ok(field_type) => field_type,
+                    Err(message) => {
+                        self.error(selector, message);
+                        return;
+                    }
+                },
+                _ => None,
+            };
+        }
+    }
+
+    // The struct type of an expression, as far as it is known without running it
+    fn struct_of(&mut self, expression: &ParseTree) -> Option<StructType> {
+        match expression.node_type() {
+            NodeType::Identifier => self.lookup(&expression.name())?.struct_type.clone(),
+            NodeType::FunctionCall if self.structs.contains_key(&expression.name()) => {
+                Some(StructType { name: expression.name(), dims: 0 })
+            }
+            NodeType::FunctionCall => declared_struct(self.returns.get(&expression.name())?),
+            NodeType::IndexExpression => {
+                let mut struct_type = self.struct_of(&expression.children()[0])?;
+                struct_type.dims = struct_type.dims.checked_sub(1)?;
+                Some(struct_type)
+            }
+            NodeType::MemberExpression => {
+                let struct_type = self.struct_of(&expression.children()[0])?;
+                self.field(&struct_type, &expression.name()).ok()?
+            }
+            _ => None,
+        }
+    }
+
+    // The struct type of `field` in a value of `struct_type`, if it has one,
+    // or the message for a field that is not there
+    fn field(&self, struct_type: &StructType, field: &str) -> Result<Option<StructType>, String> {
+        if struct_type.dims > 0 {
+            return Err(format!("cannot access field '{field}' of an array of '{}'", struct_type.name));
+        }
+        let Some(definition) = self.structs.get(&struct_type.name) else {
+            return Ok(None);  // an unknown type, reported where it is named
+        };
+        match definition.children().iter().find(|declared| declared.name() == field) {
+            Some(declared) => Ok(declared_struct(&declared.children()[0])),
+            None => Err(format!("struct '{}' has no field '{field}'", struct_type.name)),
+        }
+    }
+}
+
+
+impl Checker<'_> { // utility functions for scopes
+
+    fn declare(&mut self, name: String, assigned: bool, struct_type: Option<StructType>) {
+        self.scopes.last_mut().unwrap().insert(name, Variable { assigned, constant: false, struct_type });
+    }
+
+    fn declare_global(&mut self, declaration: &ParseTree, constant: bool) {
+        let name = declaration.name();
+        let struct_type = match declaration.children().first() {
+            Some(type_node) if is_type(type_node) => declared_struct(type_node),
+            Some(value) => self.struct_of(value),
+            None => None,
+        };
+        let variable = Variable { assigned: true, constant, struct_type };
+        if self.scopes[0].insert(name.clone(), variable).is_some() {
+            self.error(declaration, format!("'{name}' is declared more than once at the top level"));
+        }
+    }
+
+    fn lookup(&mut self, name: &str) -> Option<&mut Variable> {
+        self.scopes.iter_mut().rev().find_map(|scope| scope.get_mut(name))
+    }
+
+    fn error(&mut self, node: &ParseTree, message: String) {
+        let message = format!("in {}: {}", self.context, message);
+        self.errors.push(Diagnostic::new(message).or_span(node.span()));
+    }
+}
+
+
+fn is_type(node: &ParseTree) -> bool {
+    matches!(node.node_type(), NodeType::Type | NodeType::ArrayType)
+}
+
+// the struct of a type like `P` or `P{2}{3}`, None for the other types
+fn declared_struct(type_node: &ParseTree) -> Option<StructType> {
+    let mut dims = 0;
+    let mut element_type = type_node;
+    while element_type.node_type() == NodeType::ArrayType {
+        dims += 1;
+        element_type = &element_type.children()[0];
+    }
+    match element_type.token() {
+        Token::ID(name) => Some(StructType { name: name.clone(), dims }),
+        _ => None,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(source: &str) -> Vec<String> {
+        check(&crate::parse(source).unwrap()).into_iter().map(|diagnostic| diagnostic.message).collect()
+    }
+
+    #[test]
+    fn array_initialisers_have_the_declared_length() {
+        let errors = messages("func main() -> int32 [ let a : int32{3} = {1, 2}; let b : int32{2} = 5; let c : int32{2} = {1, 2}; return 0; ]");
+        assert_eq!(errors, [
+            "in function 'main': expected an array of 3 element(s) for type 'int32{3}', but got 2",
+            "in function 'main': expected an array of 2 element(s) for type 'int32{2}', but got a value of type 'int32'",
+        ]);
+    }
+
+    #[test]
+    fn huge_types_are_rejected() {
+        let errors = messages("struct Big [ data : int32{1000}, more : int32{1000} ]\n\
+                               let huge : int32{2147483647};\n\
+                               let many : Big{1000};\n\
+                               let few : Big{100};");
+        assert_eq!(errors.len(), 2, "{errors:?}");
+        assert!(errors[0].contains("'int32{2147483647}' holds 2147483647 values"));
+        assert!(errors[1].contains("'Big{1000}' holds 2000000 values"));
+    }
+
+    #[test]
+    fn calls_need_a_definition_and_the_right_number_of_arguments() {
+        let errors = messages("struct P [ x : int32, y : int32 ]\n\
+                               func f(a : int32) -> int32 [ return a; ]\n\
+                               func main() [ f(); f(1, 2); let x = f(f(1)) + g(1); let p = P(1); f(1); ]");
+        assert_eq!(errors, [
+            "in function 'main': function 'f' expects 1 argument(s), but got 0",
+            "in function 'main': function 'f' expects 1 argument(s), but got 2",
+            "in function 'main': call to undefined function 'g'",
+            "in function 'main': struct 'P' has 2 field(s), but got 1 value(s)",
+        ]);
+    }
+
+    #[test]
+    fn variables_are_assigned_before_they_are_read() {
+        let errors = messages("func main() [\n\
+                                   let a : int32 = 1; let b = a * 2; let c : int32; let d : int32; let e : int32;\n\
+                                   print c;\n\
+                                   if a < b then [ c = 1; d = 1; ] else [ c = 2; ]\n\
+                                   print c, d;\n\
+                                   while a < b [ e = 1; ]\n\
+                                   print e, x;\n\
+                                   let g : int32{2}; print g{0};\n\
+                               ]");
+        assert_eq!(errors, [
+            "in function 'main': variable 'c' is used before it is definitely assigned",
+            "in function 'main': variable 'd' is used before it is definitely assigned",
+            "in function 'main': variable 'e' is used before it is definitely assigned",
+            "in function 'main': use of undeclared variable 'x'",
+        ]);
+    }
+
+    #[test]
+    fn globals_are_shared_and_constants_fixed() {
+        let source = "const N : int32 = 2 * 3; const M : int32 = N + 1; let total = M; let g : int32;\n\
+                      func bump() [ total = total + N; g = 1; ]\n\
+                      func main() [ bump(); println total, \" \", g, \" \", M; ]";
+        let program = crate::parse(source).unwrap();
+        assert!(program.to_sexpr().starts_with("(program (const N int32 (* 2 3)) (const M int32 (+ N 1)) (let total _ M) (let g int32) (func bump"));
+        assert!(check(&program).is_empty());
+        let mut out = vec![];
+        crate::run(&program, &mut out).unwrap();
+        assert_eq!(out, b"13 1 7\n");
+
+        let errors = messages("const N : int32 = 2; let v = 1;\n\
+                               const K : int32 = v; const L : int32 = f(); const S : string = 3; const T : int32 = 1 / 0;\n\
+                               func f() -> int32 [ return 1; ]\n\
+                               func main() [ N = 3; let N = 4; N = 5; ]\n\
+                               let v = 2;");
+        assert_eq!(errors, [
+            "in constant 'K': 'v' is a variable, not a constant",
+            "in constant 'L': call to 'f' is not allowed in a constant expression",
+            "in constant 'S': constant is declared as 'string', but its value has type 'int32'",
+            "in constant 'T': division by zero",
+            "in global 'v': 'v' is declared more than once at the top level",
+            "in function 'main': cannot assign to constant 'N'",
+        ]);
+    }
+
+    #[test]
+    fn fields_must_exist_in_the_struct() {
+        assert_eq!(messages("struct P [ x : int32 ] func main() [ let p = P(1); p.z = 1; p.x = 2; ]"),
+                   ["in function 'main': struct 'P' has no field 'z'"]);
+        // through parameters, globals, arrays, nested structs and calls
+        let errors = messages("struct Q [ p : P, ps : P{2} ] struct P [ x : int32 ]\n\
+                               let g : Q;\n\
+                               func mk() -> Q [ return g; ]\n\
+                               func use(q : Q) -> int32 [ return q.p.x + q.ps{1}.y + q.ps.x + mk().p.w + P(1).v; ]\n\
+                               func main() [ let a : P{2}; a{0}.x = 3; a{0}.y = 4; g.ps{0}.q = 2; let b = a; print b{1}.x, b{1}.n; ]");
+        assert_eq!(errors, [
+            "in function 'use': struct 'P' has no field 'y'",
+            "in function 'use': cannot access field 'x' of an array of 'P'",
+            "in function 'use': struct 'P' has no field 'w'",
+            "in function 'use': struct 'P' has no field 'v'",
+            "in function 'main': struct 'P' has no field 'y'",
+            "in function 'main': struct 'P' has no field 'q'",
+            "in function 'main': struct 'P' has no field 'n'",
+        ]);
+    }
+}
@@ -0,0 +1,56 @@
+use std::fmt;
+
+use crate::parser_descent::ParseError;
+use crate::token::Span;
+
+// A problem found in a program, either a syntax error from the parser or a
+// semantic error from the checker. `span` is missing when the problem is not
+// tied to a single place in the source.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Option<Span>,
+    pub hint: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(message: String) -> Diagnostic {
+        Diagnostic {
+            message,
+            span: None,
+            hint: None,
+        }
+    }
+
+    // attach `span` unless the diagnostic already points somewhere more precise
+    pub fn or_span(mut self, span: Span) -> Diagnostic {
+        if self.span.is_none() {
+            self.span = Some(span);
+        }
+        self
+    }
+}
+
+impl From<ParseError> for Diagnostic {
+    fn from(error: ParseError) -> Diagnostic {
+        Diagnostic {
+            message: error.message,
+            span: Some(error.span),
+            hint: error.hint,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(span) = self.span {
+            write!(f, "{span}: ")?;
+        }
+        write!(f, "{}", self.message)?;
+        if let Some(hint) = &self.hint {
+            write!(f, "\n  hint: {hint}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,690 @@
+// Pretty-printer that writes a parse tree back as canonically laid out source.
+//
+// Every statement goes on a line of its own, indented by its nesting depth.
+// A line longer than `width` breaks after a comma of an argument, parameter,
+// array or print list, and continues one indentation step deeper. Comments
+// stay in front of the statement, field or definition that follows them, or
+// at the end of the line they ended. One inside a statement or header ends
+// the line there, the rest follows one step deeper. A blank line between
+// statements is kept (one for several), and functions and structs are always
+// set apart by one.
+//
+// Formatting is idempotent: formatting the output again gives the same text.
+
+use std::collections::VecDeque;
+
+use crate::lexer::Comment;
+use crate::parse_tree::{NodeType, ParseTree};
+use crate::parser_descent::precedence;
+use crate::token::Token;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BracketStyle {
+    OwnLine,   // the opening '[' of a block on a line of its own
+    SameLine,  // at the end of the `func`, `if`, `while` or `struct` line
+}
+
+#[derive(Debug, Clone)]
+pub struct FormatOptions {
+    pub width: usize,   // the longest line before a list is broken
+    pub indent: usize,  // spaces per nesting level
+    pub brackets: BracketStyle,
+}
+
+impl Default for FormatOptions {
+    fn default() -> FormatOptions {
+        FormatOptions { width: 80, indent: 4, brackets: BracketStyle::OwnLine }
+    }
+}
+
+
+// Format a tree without source text, e.g. one built by hand or by a pass
+pub fn format_tree(program: &ParseTree, options: &FormatOptions) -> String {
+    format(program, "", &[], options)
+}
+
+// Format the tree parsed from `source`, keeping its comments and blank lines
+pub fn format(program: &ParseTree, source: &str, comments: &[Comment], options: &FormatOptions) -> String {
+    let mut formatter = Formatter {
+        options,
+        source: source.chars().collect(),
+        comments: comments.iter().cloned().collect(),
+        out: String::new(),
+        level: 0,
+        last_end: 0,
+        at_start: true,
+    };
+    formatter.program(program);
+    formatter.out
+}
+
+
+// The text of a statement or header in pieces, a line may break between them.
+// Each piece records how deeply nested in lists the break in front of it is.
+// A comment inside the statement ends the piece in front of it, and the line
+// always breaks after that piece.
+struct Chunks {
+    pieces: Vec<Piece>,
+    depth: usize,
+    comments: VecDeque<Comment>,  // inside the statement, not yet placed
+}
+
+struct Piece {
+    depth: usize,
+    text: String,
+    comment: Option<String>,
+}
+
+impl Chunks {
+    fn new(text: &str) -> Chunks {
+        Chunks { pieces: vec![Piece { depth: 0, text: text.to_string(), comment: None }], depth: 0, comments: VecDeque::new() }
+    }
+
+    fn text(&mut self, text: &str) {
+        if self.pieces.last().unwrap().comment.is_some() {
+            self.allow_break();
+        }
+        self.pieces.last_mut().unwrap().text.push_str(text);
+    }
+
+    fn allow_break(&mut self) {
+        self.pieces.push(Piece { depth: self.depth, text: String::new(), comment: None });
+    }
+
+    // end the text so far with the comments that come before `offset`
+    fn comments_before(&mut self, offset: usize) {
+        while self.comments.front().is_some_and(|comment| comment.span.start < offset) {
+            let comment = self.comments.pop_front().unwrap();
+            if self.pieces.last().unwrap().comment.is_some() {
+                self.allow_break();
+            }
+            self.pieces.last_mut().unwrap().comment = Some(comment.text.trim_end().to_string());
+        }
+    }
+
+    // only the breaks nested at most `depth` lists deep, and those after a comment
+    fn up_to(&self, depth: usize) -> Vec<(String, Option<String>)> {
+        let mut pieces: Vec<(String, Option<String>)> = vec![];
+        for (i, piece) in self.pieces.iter().enumerate() {
+            match pieces.last_mut() {
+                Some((text, comment)) if i > 0 && piece.depth > depth && comment.is_none() => {
+                    text.push_str(&piece.text);
+                    comment.clone_from(&piece.comment);
+                }
+                _ => pieces.push((piece.text.clone(), piece.comment.clone())),
+            }
+        }
+        pieces
+    }
+}
+
+
+struct Formatter<'a> {
+    options: &'a FormatOptions,
+    source: Vec<char>,
+    comments: VecDeque<Comment>,  // not yet written, in source order
+    out: String,
+    level: usize,
+    last_end: usize,  // source offset after the last statement or comment written
+    at_start: bool,   // nothing written yet at this level
+}
+
+impl Formatter<'_> {
+    // Layout of definitions, blocks and statements
+
+    fn program(&mut self, program: &ParseTree) {
+        let items = program.children();
+        for (i, item) in items.iter().enumerate() {
+            let definition = |tree: &ParseTree| matches!(tree.node_type(), NodeType::FunctionDefinition | NodeType::StructDefinition);
+            let set_apart = i > 0 && (definition(item) || definition(&items[i - 1]));
+            self.separate(item.span().start, set_apart);
+            match item.node_type() {
+                NodeType::FunctionDefinition => self.function(item),
+                NodeType::StructDefinition => self.structure(item),
+                _ => self.statement(item),
+            }
+            let next = items.get(i + 1).map_or(usize::MAX, |next| next.span().start);
+            self.finish(item.span().end, next);
+        }
+        self.separate(usize::MAX, false);
+    }
+
+    fn function(&mut self, function: &ParseTree) {
+        let children = function.children();
+        let body = children.last().unwrap();
+        let mut header = self.chunks(&format!("func {}(", function.name()), body.span().start);
+        for (i, parameter) in children[0].children().iter().enumerate() {
+            if i > 0 {
+                header.text(",");
+                header.allow_break();
+                header.text(" ");
+            }
+            header.comments_before(parameter.span().start);
+            header.text(&format!("{} : {}", parameter.name(), type_text(&parameter.children()[0])));
+        }
+        header.text(")");
+        if children.len() == 3 {
+            header.comments_before(children[1].span().start);
+            header.text(&format!(" -> {}", type_text(&children[1])));
+        }
+        self.open_block(Some(header), body.span().start, body);
+        self.statements(body);
+        self.close_block(body);
+        self.line("]");
+    }
+
+    fn structure(&mut self, structure: &ParseTree) {
+        let bracket = self.find('[', structure.span().start);
+        let header = self.chunks(&format!("struct {}", structure.name()), bracket);
+        self.open_block(Some(header), bracket, structure);
+        let fields = structure.children();
+        for (i, field) in fields.iter().enumerate() {
+            self.separate(field.span().start, false);
+            let comma = if i + 1 < fields.len() { "," } else { "" };
+            self.line(&format!("{} : {}{comma}", field.name(), type_text(&field.children()[0])));
+            let next = fields.get(i + 1).map_or(structure.span().end, |next| next.span().start);
+            self.finish(field.span().end, next);
+        }
+        self.close_block(structure);
+        self.line("]");
+    }
+
+    fn statements(&mut self, block: &ParseTree) {
+        let statements = block.children();
+        for (i, statement) in statements.iter().enumerate() {
+            self.separate(statement.span().start, false);
+            self.statement(statement);
+            let next = statements.get(i + 1).map_or(block.span().end, |next| next.span().start);
+            self.finish(statement.span().end, next);
+        }
+    }
+
+    fn statement(&mut self, statement: &ParseTree) {
+        let children = statement.children();
+        let end = statement.span().end;
+        let mut chunks = match statement.node_type() {
+            NodeType::Block => {
+                self.open_block(None, statement.span().start, statement);
+                self.statements(statement);
+                self.close_block(statement);
+                self.line("]");
+                return;
+            }
+            NodeType::IfStatement => {
+                self.if_statement("", statement, VecDeque::new());
+                return;
+            }
+            NodeType::WhileStatement => {
+                let mut header = self.chunks("while ", children[1].span().start);
+                expression(&children[0], &mut header);
+                self.open_block(Some(header), children[1].span().start, &children[1]);
+                self.statements(&children[1]);
+                self.close_block(&children[1]);
+                self.line("]");
+                return;
+            }
+            NodeType::VariableDeclaration => {
+                let mut chunks = self.chunks(&format!("let {}", statement.name()), end);
+                for child in children {
+                    match child.node_type() {
+                        NodeType::Type | NodeType::ArrayType => chunks.text(&format!(" : {}", type_text(child))),
+                        _ => {
+                            chunks.text(" = ");
+                            expression(child, &mut chunks);
+                        }
+                    }
+                }
+                chunks
+            }
+            NodeType::ConstantDeclaration => {
+                let mut chunks = self.chunks(&format!("const {} : {} = ", statement.name(), type_text(&children[0])), end);
+                expression(&children[1], &mut chunks);
+                chunks
+            }
+            NodeType::AssignmentStatement => {
+                let mut chunks = self.chunks(&statement.name(), end);
+                let (value, selectors) = children.split_last().unwrap();
+                for selector in selectors {
+                    if selector.node_type() == NodeType::Index {
+                        chunks.text("{");
+                        expression(&selector.children()[0], &mut chunks);
+                        chunks.text("}");
+                    } else {
+                        chunks.text(&format!(".{}", selector.name()));
+                    }
+                }
+                chunks.text(" = ");
+                expression(value, &mut chunks);
+                chunks
+            }
+            NodeType::ReturnStatement => {
+                let mut chunks = self.chunks("return ", end);
+                expression(&children[0], &mut chunks);
+                chunks
+            }
+            NodeType::PrintStatement => {
+                let mut chunks = self.chunks(&statement.token().text(), end);
+                if !children.is_empty() {
+                    chunks.text(" ");
+                    list(children, &mut chunks);
+                }
+                chunks
+            }
+            NodeType::BreakStatement => self.chunks("break", end),
+            NodeType::ContinueStatement => self.chunks("continue", end),
+            NodeType::Error => {
+                self.line("// syntax error");
+                return;
+            }
+            _ => {
+                let mut chunks = self.chunks("", end);
+                expression(statement, &mut chunks);
+                chunks
+            }
+        };
+        chunks.text(";");
+        self.fill(chunks);
+    }
+
+    // `prefix` is "else " or "] else " for the `if` of an `else if`, `between`
+    // the comments from the ']' before it up to the `if`
+    fn if_statement(&mut self, prefix: &str, statement: &ParseTree, between: VecDeque<Comment>) {
+        let children = statement.children();
+        let mut header = self.chunks(&format!("{prefix}if "), children[1].span().start);
+        expression(&children[0], &mut header);
+        header.comments = between.into_iter().chain(header.comments).collect();
+        self.open_block(Some(header), children[1].span().start, &children[1]);
+        self.statements(&children[1]);
+        self.close_block(&children[1]);
+        let Some(otherwise) = children.get(2) else {
+            self.line("]");
+            return;
+        };
+        // Comments in front of the `else` follow the ']' on its own line, those
+        // after it, and all of them with `] else`, go with the else header
+        let end = children[1].span().end;
+        let keyword = self.find('e', end);
+        let mut between = self.take_comments(otherwise.span().start);
+        let prefix = match self.options.brackets {
+            BracketStyle::OwnLine => {
+                self.line("]");
+                while between.front().is_some_and(|comment| comment.span.start < keyword) {
+                    let comment = between.pop_front().unwrap();
+                    if self.source[end..comment.span.start].contains(&'\n') {
+                        self.line(comment.text.trim_end());
+                    } else {
+                        self.trail(&comment);
+                    }
+                }
+                "else "
+            }
+            BracketStyle::SameLine => "] else ",
+        };
+        if otherwise.node_type() == NodeType::IfStatement {
+            self.if_statement(prefix, otherwise, between);
+        } else {
+            let mut header = Chunks::new(prefix.trim_end());
+            header.comments = between;
+            self.open_block(Some(header), otherwise.span().start, otherwise);
+            self.statements(otherwise);
+            self.close_block(otherwise);
+            self.line("]");
+        }
+    }
+
+    // The header line of a block and its '[' at `bracket`, then one level
+    // deeper. A comment after the '[' on its line stays at the end of it.
+    fn open_block(&mut self, header: Option<Chunks>, bracket: usize, block: &ParseTree) {
+        let mut commented = false;
+        match (header, self.options.brackets) {
+            (None, _) => self.line("["),
+            (Some(header), BracketStyle::OwnLine) => {
+                self.fill(header);
+                self.line("[");
+            }
+            (Some(mut header), BracketStyle::SameLine) => {
+                // the comments of the header follow the '['
+                header.text(" [");
+                header.comments_before(usize::MAX);
+                commented = header.pieces.last().unwrap().comment.is_some();
+                self.fill(header);
+            }
+        }
+        self.level += 1;
+        self.at_start = true;
+        let first = block.children().first().map_or(block.span().end, |child| child.span().start);
+        if let Some(comment) = self.comments.front().filter(|_| !commented) {
+            let start = comment.span.start;
+            if start > bracket && start < first && !self.source[bracket..start].contains(&'\n') {
+                let comment = self.comments.pop_front().unwrap();
+                self.trail(&comment);
+            }
+        }
+    }
+
+    // the comments left at the end of the block, the caller writes its ']'
+    fn close_block(&mut self, block: &ParseTree) {
+        self.separate(block.span().end, false);
+        self.level -= 1;
+        self.at_start = false;
+        self.last_end = self.last_end.max(block.span().end);
+    }
+
+
+    // Comments, blank lines and line breaks
+
+    // the first text of a statement or header, with the comments inside it,
+    // which end before `end`
+    fn chunks(&mut self, text: &str, end: usize) -> Chunks {
+        let mut chunks = Chunks::new(text);
+        chunks.comments = self.take_comments(end);
+        chunks
+    }
+
+    fn take_comments(&mut self, end: usize) -> VecDeque<Comment> {
+        let mut taken = VecDeque::new();
+        while self.comments.front().is_some_and(|comment| comment.span.start < end) {
+            let comment = self.comments.pop_front().unwrap();
+            self.last_end = self.last_end.max(comment.span.end);
+            taken.push_back(comment);
+        }
+        taken
+    }
+
+    // the offset of `c` at or after `start`, outside of comments
+    fn find(&self, c: char, start: usize) -> usize {
+        let mut offset = start;
+        while offset < self.source.len() && self.source[offset] != c {
+            offset = match self.comments.iter().find(|comment| comment.span.start == offset) {
+                Some(comment) => comment.span.end,
+                None => offset + 1,
+            };
+        }
+        offset
+    }
+
+    // Write the comments before `start` and the blank line, if any, in front of
+    // the element starting there. `set_apart` asks for a blank line anyway.
+    fn separate(&mut self, start: usize, set_apart: bool) {
+        let mut blank = set_apart;
+        while self.comments.front().is_some_and(|comment| comment.span.start < start) {
+            let comment = self.comments.pop_front().unwrap();
+            if (blank || self.blank_line_before(comment.span.start)) && !self.at_start {
+                self.out.push('\n');
+            }
+            blank = false;
+            self.line(comment.text.trim_end());
+            self.last_end = self.last_end.max(comment.span.end);
+            self.at_start = false;
+        }
+        if (blank || self.blank_line_before(start)) && !self.at_start {
+            self.out.push('\n');
+        }
+    }
+
+    // After an element ending at `end`: move a comment on the same line, before
+    // the element starting at `next`, to the end of the line just written
+    fn finish(&mut self, end: usize, next: usize) {
+        self.last_end = self.last_end.max(end);
+        self.at_start = false;
+        let Some(comment) = self.comments.front() else {
+            return;
+        };
+        let start = comment.span.start;
+        if start >= end && start < next && !self.source[end..start].contains(&'\n') {
+            let comment = self.comments.pop_front().unwrap();
+            self.trail(&comment);
+        }
+    }
+
+    // a comment at the end of the line just written
+    fn trail(&mut self, comment: &Comment) {
+        self.out.pop();
+        self.out += &format!(" {}\n", comment.text.trim_end());
+        self.last_end = self.last_end.max(comment.span.end);
+    }
+
+    fn blank_line_before(&self, start: usize) -> bool {
+        let end = start.min(self.source.len());
+        let from = self.last_end.min(end);
+        self.source[from..end].iter().filter(|&&c| c == '\n').count() >= 2
+    }
+
+    fn line(&mut self, text: &str) {
+        self.fill(Chunks::new(text));
+    }
+
+    // Write the chunks on as few lines as fit in the width, lines after the
+    // first one indentation step deeper. The outermost list breaks first, an
+    // inner one only when an item of the outer one does not fit on a line.
+    // A comment ends its line, those after the last piece end the statement.
+    fn fill(&mut self, mut chunks: Chunks) {
+        chunks.comments_before(usize::MAX);
+        let indent = self.level * self.options.indent;
+        let room = self.options.width.saturating_sub(indent + self.options.indent);
+        let outermost = chunks.pieces.iter().skip(1).map(|piece| piece.depth).min().unwrap_or(0);
+        let mut pieces = chunks.up_to(outermost);
+        if pieces.iter().any(|(piece, _)| piece.trim_start().chars().count() > room) {
+            pieces = chunks.up_to(usize::MAX);
+        }
+        let mut line = " ".repeat(indent);
+        let mut column = indent;
+        let mut empty = true;
+        let mut after_comment = false;
+        for (chunk, comment) in pieces.iter().filter(|(chunk, comment)| !chunk.is_empty() || comment.is_some()) {
+            let mut chunk = chunk.as_str();
+            let length = chunk.chars().count();
+            let too_long = column + length > self.options.width && !chunk.trim().is_empty();
+            if !empty && (after_comment || too_long) {
+                self.out += line.trim_end();
+                self.out.push('\n');
+                column = indent + self.options.indent;
+                line = " ".repeat(column);
+                chunk = chunk.trim_start();
+            }
+            line += chunk;
+            column += chunk.chars().count();
+            empty = false;
+            after_comment = comment.is_some();
+            if let Some(comment) = comment {
+                line = match line.trim_end() {
+                    "" => format!("{line}{comment}"),
+                    text => format!("{text} {comment}"),
+                };
+            }
+        }
+        self.out += line.trim_end();
+        self.out.push('\n');
+    }
+}
+
+
+// Expressions
+
+// how tightly a node holds on to its operands, higher binds tighter
+fn binding(tree: &ParseTree) -> u8 {
+    match tree.node_type() {
+        NodeType::BinaryExpression => precedence(tree.token()),
+        NodeType::UnaryExpression => 4,
+        _ => 5,
+    }
+}
+
+fn expression(tree: &ParseTree, chunks: &mut Chunks) {
+    chunks.comments_before(tree.span().start);
+    let children = tree.children();
+    match tree.node_type() {
+        NodeType::Literal => chunks.text(&match tree.token() {
+            Token::LIT_INT32(value) => value.to_string(),
+            // digits, a point and digits, as the lexer reads them: 2.0, not 2 or 1e20
+            Token::LIT_FLT32(value) if value.fract() == 0.0 => format!("{value}.0"),
+            Token::LIT_FLT32(value) => value.to_string(),
+            Token::LIT_CHAR(value) => format!("'{value}'"),
+            Token::LIT_STRING(value) => format!("\"{value}\""),
+            token => token.text(),
+        }),
+        NodeType::Identifier => chunks.text(&tree.name()),
+        NodeType::FunctionCall => {
+            chunks.text(&format!("{}(", tree.name()));
+            list(children, chunks);
+            chunks.text(")");
+        }
+        NodeType::ArrayLiteral => {
+            chunks.text("{");
+            list(children, chunks);
+            chunks.text("}");
+        }
+        NodeType::IndexExpression => {
+            operand(&children[0], 5, chunks);
+            chunks.text("{");
+            expression(&children[1], chunks);
+            chunks.text("}");
+        }
+        NodeType::MemberExpression => {
+            operand(&children[0], 5, chunks);
+            chunks.text(&format!(".{}", tree.name()));
+        }
+        NodeType::UnaryExpression => {
+            chunks.text(&tree.token().text());
+            if children[0].node_type() == NodeType::UnaryExpression {
+                chunks.text(" ");  // - -x, not --x
+            }
+            operand(&children[0], 4, chunks);
+        }
+        NodeType::BinaryExpression => {
+            let binds = precedence(tree.token());
+            // comparisons do not chain, so a comparison inside one needs parentheses
+            let left = if binds == 1 { 2 } else { binds };
+            operand(&children[0], left, chunks);
+            chunks.text(&format!(" {} ", tree.token().text()));
+            operand(&children[1], binds + 1, chunks);
+        }
+        _ => chunks.text(&tree.node_string()),
+    }
+}
+
+// `tree` in parentheses unless it binds at least as tightly as `binds`
+fn operand(tree: &ParseTree, binds: u8, chunks: &mut Chunks) {
+    chunks.comments_before(tree.span().start);
+    if binding(tree) < binds {
+        chunks.text("(");
+        expression(tree, chunks);
+        chunks.text(")");
+    } else {
+        expression(tree, chunks);
+    }
+}
+
+// comma separated, a line may break after each comma
+fn list(items: &[Box<ParseTree>], chunks: &mut Chunks) {
+    chunks.depth += 1;
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            chunks.text(",");
+            chunks.allow_break();
+            chunks.text(" ");
+        }
+        expression(item, chunks);
+    }
+    chunks.depth -= 1;
+}
+
+// int32, Point, int32{3}{2}: the outermost size first, as in the source
+fn type_text(tree: &ParseTree) -> String {
+    let mut sizes = String::new();
+    let mut tree = tree;
+    while tree.node_type() == NodeType::ArrayType {
+        if let Token::LIT_INT32(size) = tree.token() {
+            sizes += &format!("{{{size}}}");
+        }
+        tree = &tree.children()[0];
+    }
+    format!("{}{sizes}", tree.token().text())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // comments in headers, between the parts of a statement and around `else`
+    const COMMENTED: &str = "\
+// leading
+struct Point // after the name
+[ x : flt32, // x
+  y : flt32 ]
+
+func add(x : int32, // first
+         y : int32) -> int32 [ // open
+    if x > 0 [ print x; ] // after then
+    else [ print 0; ]
+    foo(1, // arg
+        2);
+    let values : int32{3} = {1, // one
+        2, 3};
+    while x > // the condition
+        0 [ x = x - 1; ]
+    return x + // plus
+        y;
+]
+// the end
+";
+
+    fn format_text(source: &str, options: &FormatOptions) -> String {
+        crate::format(source, options).unwrap()
+    }
+
+    fn comments(source: &str) -> usize {
+        source.matches("//").count()
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        for source in [include_str!("../samples/sum.txt"), COMMENTED] {
+            for brackets in [BracketStyle::OwnLine, BracketStyle::SameLine] {
+                for width in [80, 20, 1] {
+                    let options = FormatOptions { width, brackets, ..FormatOptions::default() };
+                    let formatted = format_text(source, &options);
+                    assert_eq!(format_text(&formatted, &options), formatted, "{brackets:?}, width {width}");
+                    let tree = |source: &str| crate::parse(source).unwrap().to_sexpr();
+                    assert_eq!(tree(&formatted), tree(source));
+                    assert_eq!(comments(&formatted), comments(source));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn comments_stay_in_their_statement() {
+        let formatted = format_text(COMMENTED, &FormatOptions::default());
+        for line in [
+            "    ] // after then\n    else\n",
+            "    foo(1, // arg\n        2);\n",
+            "    let values : int32{3} = {1, // one\n        2, 3};\n",
+            "    while x > // the condition\n        0\n",
+            "    return x + // plus\n        y;\n",
+            "struct Point // after the name\n[\n",
+            "func add(x : int32, // first\n    y : int32) -> int32\n[ // open\n",
+        ] {
+            assert!(formatted.contains(line), "{line:?} not in\n{formatted}");
+        }
+        let same_line = FormatOptions { brackets: BracketStyle::SameLine, ..FormatOptions::default() };
+        let formatted = format_text(COMMENTED, &same_line);
+        assert!(formatted.contains("    ] else [ // after then\n        print 0;\n"), "{formatted}");
+        assert!(formatted.contains("struct Point [ // after the name\n"), "{formatted}");
+    }
+
+    #[test]
+    fn long_lists_break_after_a_comma() {
+        let source = "func main() [ println \"first\", add(1, 2), \"second\"; ]";
+        let options = FormatOptions { width: 20, ..FormatOptions::default() };
+        assert_eq!(format_text(source, &options), "\
+func main()
+[
+    println \"first\",
+        add(1, 2),
+        \"second\";
+]
+");
+    }
+}
@@ -0,0 +1,225 @@
+// Syntax highlighting from the lexer's tokens, as HTML or as ANSI coloured
+// terminal text. Every token gets the class of its kind, see `token_class`,
+// in HTML as `<span class="hl-keyword">` and so on, styled by `CSS`.
+//
+// Diagnostics are laid over the tokens they point at: underlined in red, with
+// the message as a tooltip in HTML. A diagnostic at the end of the input, or
+// with an empty span, marks a single space at its position.
+
+use crate::diagnostic::Diagnostic;
+use crate::lexer::Lexer;
+use crate::token::Token;
+
+// A stylesheet for `to_html`, included by `to_html_document`
+pub const CSS: &str = "\
+pre.hl { background: #fafafa; padding: 0.5em; }
+.hl-keyword { color: #a626a4; font-weight: bold; }
+.hl-type { color: #0184bc; }
+.hl-number { color: #986801; }
+.hl-string { color: #50a14f; }
+.hl-operator { color: #4078f2; }
+.hl-comment { color: #a0a1a7; font-style: italic; }
+.hl-error { text-decoration: red wavy underline; background: #fde8e8; }
+ul.hl-diagnostics { color: #c0392b; font-family: monospace; }";
+
+
+// The class of a token: keyword, type, identifier, number, string, operator
+// or punctuation
+pub fn token_class(token: &Token) -> &'static str {
+    match token {
+        Token::FUNC | Token::LET | Token::CONST | Token::STRUCT | Token::IF | Token::THEN | Token::ELSE
+        | Token::WHILE | Token::PRINT | Token::PRINTLN | Token::RETURN | Token::BREAK | Token::CONTINUE => "keyword",
+        Token::TYPE_INT32 | Token::TYPE_FLT32 | Token::TYPE_CHAR | Token::TYPE_STRING | Token::TYPE_BOOL => "type",
+        Token::ID(_) => "identifier",
+        Token::LIT_INT32(_) | Token::LIT_FLT32(_) => "number",
+        Token::LIT_CHAR(_) | Token::LIT_STRING(_) => "string",
+        Token::ADD | Token::SUB | Token::MUL | Token::DIV | Token::EQ | Token::LT | Token::GT | Token::NEQ
+        | Token::NLT | Token::NGT | Token::NOT | Token::AND | Token::OR | Token::ASSIGN | Token::ARROW_R => "operator",
+        Token::PARENS_L | Token::PARENS_R | Token::BRACKET_L | Token::BRACKET_R | Token::BRACE_L | Token::BRACE_R
+        | Token::POINT | Token::COMMA | Token::COLON | Token::SEMICOLON | Token::EOI => "punctuation",
+    }
+}
+
+fn ansi_color(class: &str) -> Option<&'static str> {
+    match class {
+        "keyword" => Some("1;35"),
+        "type" => Some("36"),
+        "number" => Some("33"),
+        "string" => Some("32"),
+        "operator" => Some("34"),
+        "comment" => Some("90"),
+        _ => None,
+    }
+}
+
+
+// A stretch of the source with one class and at most one diagnostic
+struct Run {
+    text: String,
+    class: Option<&'static str>,
+    diagnostic: Option<usize>,
+}
+
+fn runs(source: &str, diagnostics: &[Diagnostic]) -> Vec<Run> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut lexer = Lexer::new(source.to_string());
+    lexer.collect_tokens();
+    let mut classes: Vec<Option<&'static str>> = vec![None; chars.len()];
+    for spanned in lexer.tokens() {
+        for class in classes.iter_mut().take(spanned.span.end).skip(spanned.span.start) {
+            *class = Some(token_class(&spanned.token));
+        }
+    }
+    for comment in lexer.comments() {
+        for class in classes.iter_mut().take(comment.span.end).skip(comment.span.start) {
+            *class = Some("comment");
+        }
+    }
+
+    // the first diagnostic covering each character, white space is left out so
+    // a diagnostic over several lines does not underline the indentation
+    let mut marks: Vec<Option<usize>> = vec![None; chars.len()];
+    let mut markers: Vec<(usize, usize)> = vec![];  // position and diagnostic of an empty mark
+    for (i, diagnostic) in diagnostics.iter().enumerate() {
+        let Some(span) = diagnostic.span else {
+            continue;
+        };
+        let end = span.end.min(chars.len());
+        let start = span.start.min(end);
+        let visible: Vec<usize> = (start..end).filter(|&at| !chars[at].is_whitespace()).collect();
+        if visible.is_empty() {
+            markers.push((span.start.min(chars.len()), i));
+        }
+        for at in visible {
+            marks[at].get_or_insert(i);
+        }
+    }
+
+    let mut runs: Vec<Run> = vec![];
+    for at in 0..=chars.len() {
+        for &(_, diagnostic) in markers.iter().filter(|(position, _)| *position == at) {
+            runs.push(Run { text: " ".to_string(), class: None, diagnostic: Some(diagnostic) });
+        }
+        let Some(&c) = chars.get(at) else {
+            break;
+        };
+        let class = classes[at];
+        match runs.last_mut() {
+            Some(run) if run.class == class && run.diagnostic == marks[at] && !markers.iter().any(|(position, _)| *position == at) => {
+                run.text.push(c);
+            }
+            _ => runs.push(Run { text: c.to_string(), class, diagnostic: marks[at] }),
+        }
+    }
+    runs
+}
+
+
+// The source in `<pre class="hl">`, followed by a list of the diagnostics
+pub fn to_html(source: &str, diagnostics: &[Diagnostic]) -> String {
+    let mut html = String::from("<pre class=\"hl\">");
+    for run in runs(source, diagnostics) {
+        let mut classes = vec![];
+        if let Some(class) = run.class {
+            classes.push(format!("hl-{class}"));
+        }
+        if run.diagnostic.is_some() {
+            classes.push("hl-error".to_string());
+        }
+        let text = html_escape(&run.text);
+        if classes.is_empty() {
+            html += &text;
+            continue;
+        }
+        html += &format!("<span class=\"{}\"", classes.join(" "));
+        if let Some(i) = run.diagnostic {
+            html += &format!(" title=\"{}\"", html_escape(&diagnostics[i].message));
+        }
+        html += &format!(">{text}</span>");
+    }
+    html += "</pre>\n";
+    if !diagnostics.is_empty() {
+        html += "<ul class=\"hl-diagnostics\">\n";
+        for diagnostic in diagnostics {
+            html += &format!("<li>{}</li>\n", html_escape(&diagnostic.to_string()).replace('\n', "<br>"));
+        }
+        html += "</ul>\n";
+    }
+    html
+}
+
+// `to_html` as a page of its own, with the stylesheet
+pub fn to_html_document(title: &str, source: &str, diagnostics: &[Diagnostic]) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{CSS}\n</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        html_escape(title),
+        to_html(source, diagnostics),
+    )
+}
+
+// The source with ANSI colour escapes, for a terminal
+pub fn to_ansi(source: &str, diagnostics: &[Diagnostic]) -> String {
+    let mut out = String::new();
+    for run in runs(source, diagnostics) {
+        let style = match (run.class.and_then(ansi_color), run.diagnostic) {
+            (_, Some(_)) => Some("4;31"),  // red and underlined
+            (color, None) => color,
+        };
+        match style {
+            Some(style) => out += &format!("\x1b[{style}m{}\x1b[0m", run.text),
+            None => out += &run.text,
+        }
+    }
+    out
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::Span;
+
+    fn at(start: usize, end: usize, message: &str) -> Diagnostic {
+        Diagnostic { span: Some(Span { start, end, line: 1, column: start + 1 }), ..Diagnostic::new(message.to_string()) }
+    }
+
+    #[test]
+    fn html_is_escaped() {
+        let html = to_html("let s = \"<a & b>\";", &[]);
+        assert!(html.contains("<span class=\"hl-string\">&quot;&lt;a &amp; b&gt;&quot;</span>"), "{html}");
+        assert!(html.contains("<span class=\"hl-keyword\">let</span> <span class=\"hl-identifier\">s</span>"), "{html}");
+        // in the tooltip and the list of diagnostics as well
+        let html = to_html("x < y;", &[at(2, 3, "'<' & \"x\"")]);
+        assert!(html.contains("<span class=\"hl-operator hl-error\" title=\"'&lt;' &amp; &quot;x&quot;\">&lt;</span>"), "{html}");
+        assert!(html.contains("<li>1:3: '&lt;' &amp; &quot;x&quot;</li>"), "{html}");
+        assert!(to_html_document("a<b", "", &[]).contains("<title>a&lt;b</title>"));
+    }
+
+    #[test]
+    fn ansi_colours_by_class() {
+        assert_eq!(to_ansi("let x = 1; // one", &[]),
+                   "\x1b[1;35mlet\x1b[0m x \x1b[34m=\x1b[0m \x1b[33m1\x1b[0m; \x1b[90m// one\x1b[0m");
+        // a diagnostic wins over the class
+        assert_eq!(to_ansi("let x = 1;", &[at(4, 9, "bad")]),
+                   "\x1b[1;35mlet\x1b[0m \x1b[4;31mx\x1b[0m \x1b[4;31m=\x1b[0m \x1b[4;31m1\x1b[0m;");
+    }
+
+    #[test]
+    fn diagnostics_mark_what_they_cover() {
+        // the white space inside a span is left unmarked
+        let html = to_html("a  b", &[at(0, 4, "m")]);
+        assert!(html.starts_with("<pre class=\"hl\"><span class=\"hl-identifier hl-error\" title=\"m\">a</span>  \
+                                  <span class=\"hl-identifier hl-error\" title=\"m\">b</span></pre>"), "{html}");
+        // an empty span, or one past the end, marks a space at its position
+        assert_eq!(to_ansi("ab", &[at(1, 1, "m")]), "a\x1b[4;31m \x1b[0mb");
+        assert_eq!(to_ansi("ab", &[at(5, 6, "m")]), "ab\x1b[4;31m \x1b[0m");
+        // the first diagnostic over a character wins, one without a span marks nothing
+        let html = to_html("ab", &[at(0, 1, "first"), at(0, 2, "second"), Diagnostic::new("none".to_string())]);
+        assert!(html.contains("title=\"first\">a</span><span class=\"hl-identifier hl-error\" title=\"second\">b</span>"), "{html}");
+        assert!(html.contains("<li>none</li>"), "{html}");
+    }
+}
@@ -0,0 +1,649 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+
+use crate::diagnostic::Diagnostic;
+use crate::parse_tree::{NodeType, ParseTree};
+use crate::token::Token;
+
+const MAX_CALL_DEPTH: usize = 1000;
+// Statements and expressions are run from explicit stacks, so every call of
+// the program takes the same room on the native stack however deeply its
+// code nests, about 10 KiB in a debug build. The interpreter runs on a thread
+// of its own with room for MAX_CALL_DEPTH of them, and plenty to spare.
+const STACK_SIZE: usize = MAX_CALL_DEPTH * 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(i32),
+    Flt(f32),
+    Char(char),
+    Str(String),
+    Bool(bool),
+    Array(Vec<Value>),
+    Struct(String, Vec<(String, Value)>),  // struct name, fields in declaration order
+    Unit,
+}
+
+impl Value {
+    // The name of the type as written in source, e.g. "int32" or "flt32{3}"
+    pub fn type_name(&self) -> String {
+        match self {
+            Value::Int(_) => "int32".to_string(),
+            Value::Flt(_) => "flt32".to_string(),
+            Value::Char(_) => "char".to_string(),
+            Value::Str(_) => "string".to_string(),
+            Value::Bool(_) => "bool".to_string(),
+            Value::Array(elements) => match elements.first() {
+                Some(first) => array_type_name(first.type_name(), elements.len()),
+                None => "array{0}".to_string(),
+            },
+            Value::Struct(name, _) => name.clone(),
+            Value::Unit => "unit".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Int(value) => write!(f, "{value}"),
+            Value::Flt(value) => write!(f, "{value}"),
+            Value::Char(value) => write!(f, "{value}"),
+            Value::Str(value) => write!(f, "{value}"),
+            Value::Bool(value) => write!(f, "{value}"),
+            Value::Array(elements) => {
+                write!(f, "{{")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{element}")?;
+                }
+                write!(f, "}}")
+            }
+            Value::Struct(name, fields) => {
+                write!(f, "{name}(")?;
+                for (i, (field, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{field}: {value}")?;
+                }
+                write!(f, ")")
+            }
+            Value::Unit => Ok(()),
+        }
+    }
+}
+
+// How a statement finished: normally, or by leaving a loop or function early
+enum Flow {
+    Normal,
+    Break,
+    Continue,
+    Return(Value),
+}
+
+// What is left to do in the body of a function
+enum Work<'t> {
+    Run(&'t ParseTree),
+    Loop(&'t ParseTree),  // check the condition of a while statement again
+    EndScope,             // at the end of a block
+}
+
+type RunResult<T> = Result<T, Diagnostic>;
+
+// Run the `main` function of a checked program, writing its output to `out`.
+// Globals and constants are initialised first, in the order they are declared.
+pub fn run(program: &ParseTree, out: &mut (dyn Write + Send)) -> RunResult<Value> {
+    std::thread::scope(|scope| {
+        let thread = std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, move || run_program(program, out))
+            .map_err(|error| Diagnostic::new(format!("could not start the interpreter: {error}")))?;
+        thread.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+fn run_program(program: &ParseTree, out: &mut dyn Write) -> RunResult<Value> {
+    let mut interpreter = Interpreter::new(program, out);
+    for item in program.children() {
+        if matches!(item.node_type(), NodeType::VariableDeclaration | NodeType::ConstantDeclaration) {
+            let value = interpreter.initial_value(item)?;
+            interpreter.globals.insert(item.name(), value);
+        }
+    }
+    interpreter.call("main", vec![])
+}
+
+
+struct Interpreter<'a> {
+    functions: HashMap<String, &'a ParseTree>,
+    structs: HashMap<String, &'a ParseTree>,
+    globals: HashMap<String, Value>,
+    frames: Vec<Vec<HashMap<String, Value>>>,  // one stack of scopes per active call
+    out: &'a mut dyn Write,
+}
+
+
+impl<'a> Interpreter<'a> {
+
+    fn new(program: &'a ParseTree, out: &'a mut dyn Write) -> Interpreter<'a> {
+        let definitions = |node_type| program.children().iter()
+            .filter(move |child| child.node_type() == node_type)
+            .map(|definition| (definition.name(), definition.as_ref()));
+        Interpreter {
+            functions: definitions(NodeType::FunctionDefinition).collect(),
+            structs: definitions(NodeType::StructDefinition).collect(),
+            globals: HashMap::new(),
+            frames: vec![],
+            out,
+        }
+    }
+
+    fn call(&mut self, name: &str, arguments: Vec<Value>) -> RunResult<Value> {
+        let func = match self.functions.get(name) {
+            Some(func) => *func,
+            None => return Err(error(format!("call to undefined function '{name}'"))),
+        };
+        let parameters = func.children()[0].children();
+        if parameters.len() != arguments.len() {
+            return Err(error(format!("function '{name}' expects {} argument(s), but got {}",
+                                     parameters.len(), arguments.len())));
+        }
+        if self.frames.len() >= MAX_CALL_DEPTH {
+            return Err(error(format!("call depth exceeded {MAX_CALL_DEPTH} while calling '{name}'")));
+        }
+
+        // arrays passed and returned have the declared lengths
+        for (parameter, argument) in parameters.iter().zip(&arguments) {
+            let type_node = &parameter.children()[0];
+            if !fits(type_node, argument) {
+                return Err(error(format!("parameter '{}' of '{name}' is declared as '{}', but got a value of type '{}'",
+                                         parameter.name(), type_name(type_node), argument.type_name())));
+            }
+        }
+        let scope = parameters.iter().map(|parameter| parameter.name()).zip(arguments).collect();
+        self.frames.push(vec![scope]);
+        let body = func.children().last().unwrap();
+        let flow = self.execute(body);
+        self.frames.pop();
+        match flow? {
+            Flow::Return(value) => {
+                if let [_, type_node, _] = func.children() {
+                    if !fits(type_node, &value) {
+                        return Err(error(format!("'{name}' returns '{}', but the value returned has type '{}'",
+                                                 type_name(type_node), value.type_name())));
+                    }
+                }
+                Ok(value)
+            }
+            _ => Ok(Value::Unit),
+        }
+    }
+
+    // Run the body of a function. Like expressions, statements are run from an
+    // explicit stack of pending work, so however deeply blocks, ifs and whiles
+    // nest, a call takes the same amount of the native stack.
+    fn execute(&mut self, body: &ParseTree) -> RunResult<Flow> {
+        let mut pending = vec![Work::Run(body)];
+        while let Some(work) = pending.pop() {
+            let statement = match work {
+                Work::Run(statement) | Work::Loop(statement) => statement,
+                Work::EndScope => {
+                    self.scopes().pop();
+                    continue;
+                }
+            };
+            // runtime errors point at the innermost node they come from
+            let flow = self.execute_node(statement, &mut pending).map_err(|error| error.or_span(statement.span()))?;
+            match flow {
+                Flow::Normal => {}
+                Flow::Return(value) => return Ok(Flow::Return(value)),
+                // leave the blocks inside the loop, and for a break the loop too
+                Flow::Break | Flow::Continue => {
+                    while let Some(work) = pending.pop() {
+                        match work {
+                            Work::EndScope => {
+                                self.scopes().pop();
+                            }
+                            Work::Loop(_) if matches!(flow, Flow::Break) => break,
+                            Work::Loop(statement) => {
+                                pending.push(Work::Loop(statement));
+                                break;
+                            }
+                            Work::Run(_) => {}
+                        }
+                    }
+                }
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    // A statement with a body leaves the work on it in `pending`
+    fn execute_node<'t>(&mut self, statement: &'t ParseTree, pending: &mut Vec<Work<'t>>) -> RunResult<Flow> {
+        match statement.node_type() {
+            NodeType::Block => {
+                self.scopes().push(HashMap::new());
+                pending.push(Work::EndScope);
+                pending.extend(statement.children().iter().rev().map(|child| Work::Run(child)));
+            }
+            NodeType::IfStatement => {
+                let branch = match self.condition(statement)? {
+                    true => statement.children().get(1),
+                    false => statement.children().get(2),
+                };
+                pending.extend(branch.map(|branch| Work::Run(branch)));
+            }
+            NodeType::WhileStatement => {
+                if self.condition(statement)? {
+                    pending.push(Work::Loop(statement));
+                    pending.push(Work::Run(&statement.children()[1]));
+                }
+            }
+            _ => return self.simple_statement(statement),
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn simple_statement(&mut self, statement: &ParseTree) -> RunResult<Flow> {
+        match statement.node_type() {
+            NodeType::VariableDeclaration => {
+                let value = self.initial_value(statement)?;
+                self.scopes().last_mut().unwrap().insert(statement.name(), value);
+            }
+            NodeType::AssignmentStatement => self.assign(statement)?,
+            NodeType::ReturnStatement => return Ok(Flow::Return(self.evaluate(&statement.children()[0])?)),
+            NodeType::PrintStatement => self.print(statement)?,
+            NodeType::BreakStatement => return Ok(Flow::Break),
+            NodeType::ContinueStatement => return Ok(Flow::Continue),
+            NodeType::FunctionCall => {
+                self.evaluate(statement)?;
+            }
+            node_type => return Err(error(format!("cannot execute {node_type:?}"))),
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn assign(&mut self, statement: &ParseTree) -> RunResult<()> {
+        // every child but the last is an index or member selecting the part to assign
+        let (value, selectors) = statement.children().split_last().unwrap();
+        let value = self.evaluate(value)?;
+        let mut indices = vec![];
+        for selector in selectors {
+            indices.push(match selector.node_type() {
+                NodeType::Index => self.evaluate(&selector.children()[0])?,
+                _ => Value::Unit,
+            });
+        }
+        let mut target = self.lookup(&statement.name())?;
+        for (selector, index) in selectors.iter().zip(indices) {
+            target = match selector.node_type() {
+                NodeType::Index => element(target, index)?,
+                _ => member(target, &selector.name())?,
+            };
+        }
+        if !same_shape(target, &value) {
+            return Err(error(format!("cannot assign a value of type '{}' to an array of type '{}'",
+                                     value.type_name(), target.type_name())));
+        }
+        *target = value;
+        Ok(())
+    }
+
+    fn print(&mut self, statement: &ParseTree) -> RunResult<()> {
+        let mut text = String::new();
+        for child in statement.children() {
+            text += &self.evaluate(child)?.to_string();
+        }
+        if *statement.token() == Token::PRINTLN {
+            text.push('\n');
+        }
+        self.out.write_all(text.as_bytes())
+            .map_err(|e| error(format!("cannot write output: {e}")))
+    }
+
+    // the condition of an if or while statement
+    fn condition(&mut self, statement: &ParseTree) -> RunResult<bool> {
+        match self.evaluate(&statement.children()[0])? {
+            Value::Bool(value) => Ok(value),
+            value => {
+                let keyword = if statement.node_type() == NodeType::IfStatement { "if" } else { "while" };
+                Err(error(format!("{keyword} condition must be a bool, but got {value:?}")))
+            }
+        }
+    }
+
+    // the initialiser of a declaration, or the default value of its type
+    fn initial_value(&mut self, declaration: &ParseTree) -> RunResult<Value> {
+        match declaration.children() {
+            [] => Ok(Value::Unit),
+            [type_node] if matches!(type_node.node_type(), NodeType::Type | NodeType::ArrayType) => Ok(self.default_value(type_node)),
+            [type_node, expression] => {
+                let value = self.evaluate(expression)?;
+                if !fits(type_node, &value) {
+                    return Err(error(format!("'{}' is declared as '{}', but its initial value has type '{}'",
+                                             declaration.name(), type_name(type_node), value.type_name()))
+                        .or_span(expression.span()));
+                }
+                Ok(value)
+            }
+            [.., expression] => self.evaluate(expression),
+        }
+    }
+
+    // Expressions are evaluated on explicit stacks of pending nodes and values
+    // rather than by recursion, so a tall tree, like a long chain of operators,
+    // takes no more of the native stack than a flat one. Only a call of the
+    // program goes deeper.
+    fn evaluate(&mut self, expression: &ParseTree) -> RunResult<Value> {
+        let mut pending = vec![(expression, false)];  // nodes, and whether their operands are done
+        let mut values: Vec<Value> = vec![];
+        while let Some((node, operands_done)) = pending.pop() {
+            let value = match (node.node_type(), operands_done) {
+                (NodeType::Literal, _) => literal_value(node.token()),
+                (NodeType::Identifier, _) => self.lookup(&node.name()).map_err(|error| error.or_span(node.span()))?.clone(),
+                (_, false) => {
+                    pending.push((node, true));
+                    pending.extend(node.children().iter().rev().map(|child| (child.as_ref(), false)));
+                    continue;
+                }
+                (_, true) => {
+                    let operands = values.split_off(values.len() - node.children().len());
+                    self.apply(node, operands).map_err(|error| error.or_span(node.span()))?
+                }
+            };
+            values.push(value);
+        }
+        Ok(values.pop().unwrap())
+    }
+
+    // the value of an expression node from the values of its children
+    fn apply(&mut self, expression: &ParseTree, mut operands: Vec<Value>) -> RunResult<Value> {
+        match expression.node_type() {
+            NodeType::FunctionCall => match self.structs.get(&expression.name()) {
+                Some(definition) => construct(definition, operands),
+                None => self.call(&expression.name(), operands),
+            },
+            NodeType::BinaryExpression => {
+                let right = operands.pop().unwrap();
+                binary(expression.token(), operands.pop().unwrap(), right)
+            }
+            NodeType::UnaryExpression => unary(expression.token(), operands.pop().unwrap()),
+            NodeType::ArrayLiteral => array(operands),
+            NodeType::IndexExpression => {
+                let index = operands.pop().unwrap();
+                Ok(element(&mut operands[0], index)?.clone())
+            }
+            NodeType::MemberExpression => Ok(member(&mut operands[0], &expression.name())?.clone()),
+            node_type => Err(error(format!("cannot evaluate {node_type:?}"))),
+        }
+    }
+}
+
+
+impl Interpreter<'_> { // utility functions for scopes
+
+    fn scopes(&mut self) -> &mut Vec<HashMap<String, Value>> {
+        self.frames.last_mut().unwrap()
+    }
+
+    // The value of a declared variable without an initialiser
+    fn default_value(&self, type_node: &ParseTree) -> Value {
+        match type_node.token() {
+            Token::LIT_INT32(size) => {
+                let element = self.default_value(&type_node.children()[0]);
+                Value::Array(vec![element; *size as usize])
+            }
+            Token::ID(name) => match self.structs.get(name) {
+                Some(definition) => {
+                    let fields = definition.children().iter()
+                        .map(|field| (field.name(), self.default_value(&field.children()[0])))
+                        .collect();
+                    Value::Struct(name.clone(), fields)
+                }
+                None => Value::Unit,
+            },
+            Token::TYPE_INT32 => Value::Int(0),
+            Token::TYPE_FLT32 => Value::Flt(0.0),
+            Token::TYPE_CHAR => Value::Char('\0'),
+            Token::TYPE_STRING => Value::Str(String::new()),
+            Token::TYPE_BOOL => Value::Bool(false),
+            _ => Value::Unit,
+        }
+    }
+
+    // locals of the current call shadow the globals
+    fn lookup(&mut self, name: &str) -> RunResult<&mut Value> {
+        let local = self.frames.last_mut()
+            .and_then(|scopes| scopes.iter_mut().rev().find_map(|scope| scope.get_mut(name)));
+        local.or_else(|| self.globals.get_mut(name))
+            .ok_or_else(|| error(format!("undefined variable '{name}'")))
+    }
+}
+
+
+pub(crate) fn binary(operator: &Token, left: Value, right: Value) -> RunResult<Value> {
+    let value = match (left, right) {
+        (Value::Int(a), Value::Int(b)) => match operator {
+            Token::ADD => Value::Int(a.wrapping_add(b)),
+            Token::SUB => Value::Int(a.wrapping_sub(b)),
+            Token::MUL => Value::Int(a.wrapping_mul(b)),
+            Token::DIV if b == 0 => return Err(error("division by zero".to_string())),
+            Token::DIV => Value::Int(a.wrapping_div(b)),
+            _ => compare(operator, &a, &b),
+        },
+        (Value::Flt(a), Value::Flt(b)) => match operator {
+            Token::ADD => Value::Flt(a + b),
+            Token::SUB => Value::Flt(a - b),
+            Token::MUL => Value::Flt(a * b),
+            Token::DIV => Value::Flt(a / b),
+            _ => compare(operator, &a, &b),
+        },
+        (Value::Int(a), Value::Flt(b)) => return binary(operator, Value::Flt(a as f32), Value::Flt(b)),
+        (Value::Flt(a), Value::Int(b)) => return binary(operator, Value::Flt(a), Value::Flt(b as f32)),
+        (Value::Str(a), Value::Str(b)) if *operator == Token::ADD => Value::Str(a + &b),
+        (Value::Str(a), Value::Str(b)) => compare(operator, &a, &b),
+        (Value::Char(a), Value::Char(b)) => compare(operator, &a, &b),
+        (Value::Bool(a), Value::Bool(b)) => compare(operator, &a, &b),
+        (a, b) => return Err(error(format!("cannot apply {} to {a:?} and {b:?}", operator.describe()))),
+    };
+    match value {
+        Value::Unit => Err(error(format!("cannot apply {} to these values", operator.describe()))),
+        value => Ok(value),
+    }
+}
+
+pub(crate) fn unary(operator: &Token, operand: Value) -> RunResult<Value> {
+    match (operator, operand) {
+        (Token::SUB, Value::Int(value)) => Ok(Value::Int(value.wrapping_neg())),
+        (Token::SUB, Value::Flt(value)) => Ok(Value::Flt(-value)),
+        (Token::NOT, Value::Bool(value)) => Ok(Value::Bool(!value)),
+        (operator, value) => Err(error(format!("cannot apply {} to {value:?}", operator.describe()))),
+    }
+}
+
+fn compare<T: PartialOrd>(operator: &Token, a: &T, b: &T) -> Value {
+    match operator {
+        Token::EQ => Value::Bool(a == b),
+        Token::NEQ => Value::Bool(a != b),
+        Token::LT => Value::Bool(a < b),
+        Token::GT => Value::Bool(a > b),
+        Token::NLT => Value::Bool(a >= b),
+        Token::NGT => Value::Bool(a <= b),
+        _ => Value::Unit,
+    }
+}
+
+pub(crate) fn literal_value(token: &Token) -> Value {
+    match token {
+        Token::LIT_INT32(value) => Value::Int(*value),
+        Token::LIT_FLT32(value) => Value::Flt(*value),
+        Token::LIT_CHAR(value) => Value::Char(*value),
+        Token::LIT_STRING(value) => Value::Str(value.clone()),
+        _ => Value::Unit,
+    }
+}
+
+// Build a value of the struct `definition` from its field values, in declaration order
+pub(crate) fn construct(definition: &ParseTree, values: Vec<Value>) -> RunResult<Value> {
+    let fields = definition.children();
+    if fields.len() != values.len() {
+        return Err(error(format!("struct '{}' has {} field(s), but got {} value(s)",
+                                 definition.name(), fields.len(), values.len())));
+    }
+    let fields = fields.iter().map(|field| field.name()).zip(values).collect();
+    Ok(Value::Struct(definition.name(), fields))
+}
+
+// The field `name` of a struct value
+pub(crate) fn member<'v>(value: &'v mut Value, name: &str) -> RunResult<&'v mut Value> {
+    match value {
+        Value::Struct(struct_name, fields) => {
+            let struct_name = struct_name.clone();
+            fields.iter_mut()
+                .find(|(field, _)| field == name)
+                .map(|(_, value)| value)
+                .ok_or_else(|| error(format!("struct '{struct_name}' has no field '{name}'")))
+        }
+        value => Err(error(format!("cannot access field '{name}' of a value of type {}", value.type_name()))),
+    }
+}
+
+// The name of an array type from its element type, e.g. int32{3} and 2 give int32{2}{3}
+pub(crate) fn array_type_name(element: String, length: usize) -> String {
+    match element.find('{') {
+        Some(i) => format!("{}{{{length}}}{}", &element[..i], &element[i..]),
+        None => format!("{element}{{{length}}}"),
+    }
+}
+
+// A declared type as written in source, e.g. "int32", "char{2}{8}" or "Point"
+pub(crate) fn type_name(type_node: &ParseTree) -> String {
+    match type_node.token() {
+        Token::LIT_INT32(size) => array_type_name(type_name(&type_node.children()[0]), *size as usize),
+        Token::ID(name) => name.clone(),
+        token => token.describe().trim_matches('\'').to_string(),
+    }
+}
+
+// Does `value` have the lengths of the array type, at every level
+fn fits(type_node: &ParseTree, value: &Value) -> bool {
+    match (type_node.token(), value) {
+        (Token::LIT_INT32(length), Value::Array(elements)) => {
+            elements.len() == *length as usize && elements.iter().all(|element| fits(&type_node.children()[0], element))
+        }
+        (Token::LIT_INT32(_), _) => false,
+        _ => true,
+    }
+}
+
+// An array keeps its length, replacing it needs an array of the same lengths
+fn same_shape(old: &Value, new: &Value) -> bool {
+    match (old, new) {
+        (Value::Array(old), Value::Array(new)) => {
+            old.len() == new.len() && old.iter().zip(new).all(|(old, new)| same_shape(old, new))
+        }
+        (Value::Array(_), _) => false,
+        _ => true,
+    }
+}
+
+// Build an array value, all elements must have the same type
+pub(crate) fn array(elements: Vec<Value>) -> RunResult<Value> {
+    if let Some(first) = elements.first() {
+        let expected = first.type_name();
+        if let Some(other) = elements.iter().find(|element| element.type_name() != expected) {
+            return Err(error(format!("array elements must all have the same type, but found {expected} and {}",
+                                     other.type_name())));
+        }
+    }
+    Ok(Value::Array(elements))
+}
+
+// The element of `array` at `index`, checking the index against the bounds
+pub(crate) fn element(array: &mut Value, index: Value) -> RunResult<&mut Value> {
+    let index = match index {
+        Value::Int(index) => index,
+        index => return Err(error(format!("array index must be an int32, but got {}", index.type_name()))),
+    };
+    match array {
+        Value::Array(elements) => {
+            let length = elements.len();
+            usize::try_from(index).ok()
+                .and_then(|i| elements.get_mut(i))
+                .ok_or_else(|| error(format!("index {index} is out of bounds for an array of length {length}")))
+        }
+        value => Err(error(format!("cannot index a value of type {}", value.type_name()))),
+    }
+}
+
+fn error(message: String) -> Diagnostic {
+    Diagnostic::new(message)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_source(source: &str) -> (RunResult<Value>, String) {
+        let program = crate::parse(source).unwrap();
+        assert_eq!(crate::check(&program), vec![]);
+        let mut out = vec![];
+        let result = run(&program, &mut out);
+        (result, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn runaway_recursion_stops_at_the_call_depth() {
+        let (result, _) = run_source("func f(n : int32) -> int32 [ return f(n); ]\nfunc main() -> int32 [ return f(1); ]");
+        let error = result.unwrap_err();
+        assert!(error.message.contains("call depth exceeded"), "{error}");
+    }
+
+    #[test]
+    fn recursion_within_the_call_depth_runs() {
+        let (result, out) = run_source(
+            "func count(n : int32) -> int32 [ if n == 0 then [ return 0; ] return 1 + count(n - 1); ]\n\
+             func main() -> int32 [ println count(900); return 0; ]");
+        assert_eq!(result, Ok(Value::Int(0)));
+        assert_eq!(out, "900\n");
+    }
+
+    #[test]
+    fn deepest_calls_of_the_tallest_code_run() {
+        // MAX_CALL_DEPTH calls, each nested in blocks and evaluating a chain
+        // of operators as far as the parser allows
+        let depth = 100;
+        let sum = vec!["1"; 300].join(" + ");
+        let (result, out) = run_source(&format!(
+            "func f(n : int32) -> int32 [ if n == 0 [ return 0; ] {}return f(n - 1) + {sum};{} ]\n\
+             func main() -> int32 [ println f({}); return 0; ]",
+            "[ ".repeat(depth), " ]".repeat(depth), MAX_CALL_DEPTH - 2));
+        assert_eq!(result, Ok(Value::Int(0)));
+        assert_eq!(out, format!("{}\n", (MAX_CALL_DEPTH - 2) * 300));
+    }
+
+    #[test]
+    fn arrays_keep_their_declared_length() {
+        let (result, out) = run_source("func main() -> int32 [ let a : int32{3} = {1, 2, 3}; a = {4, 5, 6}; println a; a = {7}; return 0; ]");
+        assert_eq!(out, "{4, 5, 6}\n");
+        assert_eq!(result.unwrap_err().message, "cannot assign a value of type 'int32{1}' to an array of type 'int32{3}'");
+
+        let (result, _) = run_source("func two() -> int32{2} [ return {1, 2}; ]\nfunc main() -> int32 [ let a : int32{3} = two(); return 0; ]");
+        assert_eq!(result.unwrap_err().message, "'a' is declared as 'int32{3}', but its initial value has type 'int32{2}'");
+
+        let (result, out) = run_source("func f(a : int32{3}) [ a = {1, 2, 3}; println a; ]\n\
+                                        func main() -> int32 [ f({4, 5, 6}); f({1}); return 0; ]");
+        assert_eq!(out, "{1, 2, 3}\n");
+        let error = result.unwrap_err();
+        assert_eq!(error.message, "parameter 'a' of 'f' is declared as 'int32{3}', but got a value of type 'int32{1}'");
+        assert_eq!(error.span.map(|span| span.column), Some(38));  // the call f({1})
+
+        let (result, _) = run_source("func three() -> int32{3} [ return {1}; ]\nfunc main() -> int32 [ println three(); return 0; ]");
+        assert_eq!(result.unwrap_err().message, "'three' returns 'int32{3}', but the value returned has type 'int32{1}'");
+    }
+}
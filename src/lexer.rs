@@ -1,9 +1,9 @@
 // CS 1163
 // Chris Perrone
 
-use crate::token::Token;
-
-#[allow(non_camel_case_types, dead_code)]
+use crate::parser_descent::ParseError;
+use crate::token::{Span, SpannedToken, Token};
+use crate::token_source::{TokenSource, TokenStream};
 
 #[allow(dead_code)]
 enum LexerState {
@@ -18,51 +18,86 @@ enum LexerState {
 }
 
 #[allow(dead_code)]
-pub(crate) struct Lexer {
-    input: String,
+pub struct Lexer {
+    input: Vec<char>,
     pos: usize,
     state: LexerState,
     current_token: Token,
     buffer: String,
+    start: usize,
     tokens: Vec<Token>,
+    spans: Vec<Span>,
+    comments: Vec<(usize, usize)>,  // start..end of every `//` comment
+    errors: Vec<(usize, usize, String)>,  // start..end and message of every malformed token
+    stream: TokenStream,  // the collected tokens as handed to the parser
+}
+
+
+// A `//` comment running to the end of its line, the text includes the `//`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    pub text: String,
+    pub span: Span,
 }
 
 #[allow(dead_code)]
 impl Lexer {
-    pub(crate) fn new(input: String) -> Self {
+    pub fn new(input: String) -> Self {
         Self {
-            input,
+            input: input.chars().collect(),
             pos: 0,
             state: LexerState::Start,
             current_token: Token::EOI,
             buffer: String::new(),
+            start: 0,
             tokens: Vec::new(),
+            spans: Vec::new(),
+            comments: Vec::new(),
+            errors: Vec::new(),
+            stream: TokenStream::new(vec![]),
         }
     }
 
-    pub(crate) fn set_input(&mut self, input: String) {
-        self.input = input;
+    pub fn set_input(&mut self, input: String) {
+        self.input = input.chars().collect();
         self.pos = 0;
         self.state = LexerState::Start;
         self.current_token = Token::EOI;
         self.buffer.clear();
+        self.start = 0;
         self.tokens.clear();
+        self.spans.clear();
+        self.comments.clear();
+        self.errors.clear();
+        self.stream = TokenStream::new(vec![]);
     }
 
-    pub(crate) fn advance(&mut self) -> Token {
+    pub fn advance(&mut self) -> Token {
         loop {
             if self.pos >= self.input.len() {
-                self.state = LexerState::End;
-                self.current_token = Token::EOI;
-                let token = Token::EOI;
-                self.tokens.push(token.clone());
-                return token;
+                match self.state {
+                    // the end of input also ends a pending identifier, number or "-"
+                    LexerState::InIdentifier | LexerState::InNumber | LexerState::ArrowR => {}
+                    // keep what was read, the error points at the opening quote
+                    LexerState::InStringLiteral => {
+                        self.errors.push((self.start, self.start + 1, "unterminated string literal, this '\"' is never closed".to_string()));
+                        self.state = LexerState::Start;
+                        return Token::LIT_STRING(std::mem::take(&mut self.buffer));
+                    }
+                    _ => {
+                        self.state = LexerState::End;
+                        self.current_token = Token::EOI;
+                        self.start = self.pos;
+                        return Token::EOI;
+                    }
+                }
             }
 
-            let c = self.input.chars().nth(self.pos).unwrap();
+            let c = self.input.get(self.pos).copied().unwrap_or(' ');
 
             match self.state {
                 LexerState::Start => {
+                    self.start = self.pos;  // remember where the next token begins
                     match c {
                         '(' => {
                             self.state = LexerState::Start;
@@ -98,6 +133,21 @@ impl Lexer {
                             self.state = LexerState::InStringLiteral;
                             self.pos += 1;
                         }
+                        '\'' => {
+                            self.state = LexerState::Start;
+                            // a single character between quotes, 'a'
+                            if let (Some(&value), Some('\'')) = (self.input.get(self.pos + 1), self.input.get(self.pos + 2)) {
+                                self.pos += 3;
+                                return Token::LIT_CHAR(value);
+                            }
+                            // skip to the closing quote on the same line, if any
+                            let rest = self.input[self.pos + 1..].iter().take_while(|&&c| c != '\n');
+                            self.pos += match rest.clone().position(|&c| c == '\'') {
+                                Some(length) => length + 2,
+                                None => 1,
+                            };
+                            self.errors.push((self.start, self.pos, "a char literal is one character between quotes, like 'a'".to_string()));
+                        }
                         '.' => {
                             self.state = LexerState::Start;
                             self.pos += 1;
@@ -121,13 +171,30 @@ impl Lexer {
                         '>' => {
                             self.state = LexerState::Start;
                             self.pos += 1;
+                            if self.next_is('=') {
+                                self.pos += 1;
+                                return Token::NLT;
+                            }
                             return Token::GT;
                         }
                         '<' => {
                             self.state = LexerState::Start;
                             self.pos += 1;
+                            if self.next_is('=') {
+                                self.pos += 1;
+                                return Token::NGT;
+                            }
                             return Token::LT;
                         }
+                        '!' => {
+                            self.state = LexerState::Start;
+                            self.pos += 1;
+                            if self.next_is('=') {
+                                self.pos += 1;
+                                return Token::NEQ;
+                            }
+                            return Token::NOT;
+                        }
                         '+' => {
                             self.state = LexerState::Start;
                             self.pos += 1;
@@ -146,14 +213,26 @@ impl Lexer {
                         '/' => {
                             self.state = LexerState::Start;
                             self.pos += 1;
+                            if self.next_is('/') {
+                                // a comment, skipped like white space up to the end of the line
+                                while self.pos < self.input.len() && self.input[self.pos] != '\n' {
+                                    self.pos += 1;
+                                }
+                                self.comments.push((self.start, self.pos));
+                                continue;
+                            }
                             return Token::DIV;
                         }
                         '=' => {
                             self.state = LexerState::Start;
                             self.pos += 1;
+                            if self.next_is('=') {
+                                self.pos += 1;
+                                return Token::EQ;
+                            }
                             return Token::ASSIGN;
                         }
-                        'a'..='z' | 'A'..='Z' => {
+                        'a'..='z' | 'A'..='Z' | '_' => {
                             self.state = LexerState::InIdentifier;
                             self.buffer.push(c);
                             self.pos += 1;
@@ -163,14 +242,22 @@ impl Lexer {
                             self.buffer.push(c);
                             self.pos += 1;
                         }
-                        _ => {
+                        c if c.is_whitespace() => {
                             self.pos += 1;
                         }
+                        _ => {
+                            // characters that start no token, a run of them is one error
+                            let length = self.input[self.pos..].iter().take_while(|&&c| !starts_token(c)).count();
+                            self.pos += length;
+                            let text: String = self.input[self.start..self.pos].iter().collect();
+                            let plural = if length > 1 { "s" } else { "" };
+                            self.errors.push((self.start, self.pos, format!("unexpected character{plural} '{text}'")));
+                        }
                     }
                 }
                 LexerState::InIdentifier => {
                     match c {
-                        'a'..='z' | 'A'..='Z' | '0'..='9' => {
+                        'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => {
                             self.buffer.push(c);
                             self.pos += 1;
                         }
@@ -182,6 +269,10 @@ impl Lexer {
                                 return Token::FUNC;
                             } else if word == "let" {
                                 return Token::LET;
+                            } else if word == "const" {
+                                return Token::CONST;
+                            } else if word == "struct" {
+                                return Token::STRUCT;
                             } else if word == "if" {
                                 return Token::IF;
                             } else if word == "then" {
@@ -192,14 +283,24 @@ impl Lexer {
                                 return Token::WHILE;
                             } else if word == "print" {
                                 return Token::PRINT;
+                            } else if word == "println" {
+                                return Token::PRINTLN;
                             } else if word == "return" {
                                 return Token::RETURN;
+                            } else if word == "break" {
+                                return Token::BREAK;
+                            } else if word == "continue" {
+                                return Token::CONTINUE;
                             } else if word == "int32" {
                                 return Token::TYPE_INT32;
                             } else if word == "flt32" {
                                 return Token::TYPE_FLT32;
                             } else if word == "char" {
                                 return Token::TYPE_CHAR;
+                            } else if word == "string" {
+                                return Token::TYPE_STRING;
+                            } else if word == "bool" {
+                                return Token::TYPE_BOOL;
                             } else {
                                 return Token::ID(word);
                             }
@@ -212,8 +313,27 @@ impl Lexer {
                             self.buffer.push(c);
                             self.pos += 1;
                         }
+                        // a point followed by a digit makes it a float, 1.5 but not 1.x
+                        '.' if !self.buffer.contains('.') && self.input.get(self.pos + 1).is_some_and(char::is_ascii_digit) => {
+                            self.buffer.push(c);
+                            self.pos += 1;
+                        }
+                        _ if self.buffer.contains('.') => {
+                            let num = self.buffer.parse::<f32>().unwrap_or(f32::INFINITY);
+                            if num.is_infinite() {
+                                let message = format!("float literal '{}' is too large for flt32", self.buffer);
+                                self.errors.push((self.start, self.pos, message));
+                            }
+                            self.buffer.clear();
+                            self.state = LexerState::Start;
+                            return Token::LIT_FLT32(num);
+                        }
                         _ => {
-                            let num = self.buffer.parse::<i32>().unwrap();
+                            let num = self.buffer.parse::<i32>().unwrap_or_else(|_| {
+                                let message = format!("integer literal '{}' is too large, int32 goes up to {}", self.buffer, i32::MAX);
+                                self.errors.push((self.start, self.pos, message));
+                                0
+                            });
                             self.buffer.clear();
                             self.state = LexerState::Start;
                             return Token::LIT_INT32(num);
@@ -236,28 +356,16 @@ impl Lexer {
                     }
                 }
                 LexerState::ArrowR => {
+                    self.buffer.clear();
+                    self.state = LexerState::Start;
                     match c {
                         '>' => {
-                            self.state = LexerState::Start;
-                            self.buffer.push(c);
-                            self.pos += 1;
-                            if self.buffer.clone() == "->" {
-                                self.pos += 1; // Advance to the next character after identifying "->"
-                                self.buffer.clear();
-                                return Token::ARROW_R;
-                            }
+                            self.pos += 1; // Advance to the next character after identifying "->"
+                            return Token::ARROW_R;
                         }
                         _ => {
-                            let word = self.buffer.clone();
-                            self.buffer.clear();
-                            self.state = LexerState::Start;
-                            if word == "->" {
-                                self.pos += 1; // Advance to the next character after identifying "->"
-                                return Token::ARROW_R;
-                            } else {
-                                self.pos += 1; // Advance to the next character after identifying "-"
-                                return Token::SUB;
-                            }
+                            // "-" on its own, the current character starts the next token
+                            return Token::SUB;
                         }
                     }
                 }
@@ -266,54 +374,177 @@ impl Lexer {
         }
     }
 
-    pub(crate) fn curr(&self) -> Token {
-        if self.pos >= self.input.len() {
-            return Token::EOI;
-        }
-        self.current_token.clone()
+    fn next_is(&self, c: char) -> bool {
+        self.input.get(self.pos) == Some(&c)
     }
 
-    pub(crate) fn peek_next(&self) -> Token {
-        if self.pos + 1 >= self.input.len() {
-            return Token::EOI;
-        }
-        self.tokens[self.pos + 1].clone()
+    pub fn tokens(&self) -> Vec<SpannedToken> {
+        self.tokens.iter().zip(&self.spans)
+            .map(|(token, span)| SpannedToken { token: token.clone(), span: *span })
+            .collect()
     }
 
-    pub(crate) fn curr1(&self) -> Token {
-        if self.pos >= self.input.len() {
-            return Token::EOI;
+    // The comments passed over so far, in source order
+    pub fn comments(&self) -> Vec<Comment> {
+        let mut comments = vec![];
+        let (mut offset, mut line, mut column) = (0, 1, 1);
+        for &(start, end) in &self.comments {
+            while offset < start {
+                if self.input[offset] == '\n' {
+                    line += 1;
+                    column = 1;
+                } else {
+                    column += 1;
+                }
+                offset += 1;
+            }
+            let text = self.input[start..end].iter().collect();
+            comments.push(Comment { text, span: Span { start, end, line, column } });
         }
-        self.tokens[self.pos].clone()
+        comments
     }
 
-    pub(crate) fn advance1(&mut self) -> Token {
-        if self.pos >= self.tokens.len() {
-            return Token::EOI;
-        }
-        self.pos += 1;
-        self.tokens[self.pos - 1].clone()
+    // The tokens that could not be read, like an integer literal out of range.
+    // Each still produced a token, so the parser carries on after it.
+    pub fn errors(&self) -> Vec<ParseError> {
+        self.errors.iter().map(|(start, end, message)| {
+            let before = &self.input[..*start];
+            let line = 1 + before.iter().filter(|&&c| c == '\n').count();
+            let column = 1 + before.iter().rev().take_while(|&&c| c != '\n').count();
+            ParseError { message: message.clone(), span: Span { start: *start, end: *end, line, column }, hint: None }
+        }).collect()
     }
 
-
-    pub(crate) fn print_tokens(&mut self) {
+    pub fn collect_tokens(&mut self) {
+        let mut offset = 0;
+        let (mut line, mut column) = (1, 1);
         loop {
             let token = self.advance();
-            println!("{:?}", token);
+            // walk the line/column counters forward to the start of the token
+            while offset < self.start {
+                if self.input[offset] == '\n' {
+                    line += 1;
+                    column = 1;
+                } else {
+                    column += 1;
+                }
+                offset += 1;
+            }
+            self.tokens.push(token.clone()); // Append the tokens to the tokens vector
+            self.spans.push(Span { start: self.start, end: self.pos, line, column });
             if token == Token::EOI {
                 break;
             }
         }
     }
+}
 
-    pub(crate) fn collect_tokens(&mut self) {
-        loop {
-            let token = self.advance();
-            self.tokens.push(token.clone()); // Append the tokens to the tokens vector
-            if token == Token::EOI {
-                break;
-            }
+
+// Can `c` begin a token, a comment or white space
+fn starts_token(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c.is_whitespace() || "()[]{}\"'.,:;<>!=+-*/".contains(c)
+}
+
+
+// The lexer hands its collected tokens to the parser one by one, through a
+// `TokenStream` of its own: `pos` is where the lexer reads the input
+impl TokenSource for Lexer {
+    fn begin(&mut self) {
+        // collect_tokens always ends with EOI, so no tokens means not yet collected
+        if self.tokens.is_empty() {
+            self.collect_tokens();
         }
-        self.pos = 0;
+        self.stream = TokenStream::new(self.tokens());
+    }
+
+    fn current(&self) -> Token {
+        self.stream.current()
+    }
+
+    fn peek_next(&self) -> Token {
+        self.stream.peek_next()
+    }
+
+    fn bump(&mut self) {
+        self.stream.bump();
+    }
+
+    fn position(&self) -> usize {
+        self.stream.position()
     }
-}
\ No newline at end of file
+
+    fn curr_span(&self) -> Span {
+        self.stream.curr_span()
+    }
+
+    fn prev_span(&self) -> Span {
+        self.stream.prev_span()
+    }
+
+    fn errors(&self) -> Vec<ParseError> {
+        Lexer::errors(self)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integer_out_of_range_is_an_error() {
+        let mut lexer = Lexer::new("let x = 99999999999;".to_string());
+        lexer.collect_tokens();
+        let errors = lexer.errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!((errors[0].span.start, errors[0].span.end, errors[0].span.column), (8, 19, 9));
+        assert!(lexer.tokens().iter().any(|spanned| spanned.token == Token::SEMICOLON));
+
+        let diagnostics = crate::parse("let x = 99999999999;").unwrap_err();
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].message.contains("too large"));
+    }
+
+    #[test]
+    fn float_and_char_literals() {
+        let tokens: Vec<Token> = crate::lex("1.5 2 p.x 3.y 'c' ','").0.into_iter().map(|spanned| spanned.token).collect();
+        assert_eq!(tokens, [
+            Token::LIT_FLT32(1.5), Token::LIT_INT32(2), Token::ID("p".to_string()), Token::POINT, Token::ID("x".to_string()),
+            Token::LIT_INT32(3), Token::POINT, Token::ID("y".to_string()), Token::LIT_CHAR('c'), Token::LIT_CHAR(','), Token::EOI,
+        ]);
+        assert!(crate::parse("struct Point [ x : flt32, y : flt32 ]\nlet y = Point(1.0, 2.0).y;").is_ok());
+
+        let mut lexer = Lexer::new("let c = 'ab';".to_string());
+        lexer.collect_tokens();
+        assert_eq!(lexer.errors().len(), 1);
+    }
+
+    fn lex_errors(source: &str) -> Vec<String> {
+        let mut lexer = Lexer::new(source.to_string());
+        lexer.collect_tokens();
+        lexer.errors().iter().map(ParseError::to_string).collect()
+    }
+
+    #[test]
+    fn unterminated_strings_are_errors() {
+        assert_eq!(lex_errors("print \"a;\nprint 1;"), ["1:7: unterminated string literal, this '\"' is never closed"]);
+        let (tokens, diagnostics) = crate::lex("print \"a;");
+        let tokens: Vec<Token> = tokens.into_iter().map(|spanned| spanned.token).collect();
+        assert_eq!(tokens, [Token::PRINT, Token::LIT_STRING("a;".to_string()), Token::EOI]);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].to_string(), "1:7: unterminated string literal, this '\"' is never closed");
+        assert!(lex_errors("print \"a\nb\";").is_empty());
+    }
+
+    #[test]
+    fn unknown_characters_are_errors() {
+        assert_eq!(lex_errors("func main() [ ] # $ @"), [
+            "1:17: unexpected character '#'", "1:19: unexpected character '$'", "1:21: unexpected character '@'",
+        ]);
+        assert_eq!(lex_errors("if a && b [ ]"), ["1:6: unexpected characters '&&'"]);
+        assert_eq!(lex_errors("let é = 1;"), ["1:5: unexpected character 'é'"]);
+        // the tokens around them are still read
+        let diagnostics = crate::parse("func main() [ ] #").unwrap_err();
+        assert_eq!(diagnostics.len(), 1);
+    }
+}
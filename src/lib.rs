@@ -0,0 +1,110 @@
+// Lexer and recursive descent parser for the course language, usable as a
+// library: `lex` turns source text into tokens (reporting malformed ones),
+// `parse` builds the parse tree (collecting every syntax error), `check` runs
+// the semantic checks, `run` interprets a checked program and `format` lays
+// the source out canonically.
+// `ParseTree::to_sexpr`/`from_sexpr` convert a tree to and from a compact
+// S-expression form for golden tests, and `SyntaxTree` holds the same tree
+// in an arena with node ids and parent links for passes that need them.
+// `Query` finds the nodes matching a tree pattern, `tree_diff::diff` the
+// edits that turn one syntax tree into another, and `highlight` renders the
+// source with coloured tokens as HTML or for a terminal.
+//
+// With the `serde` feature, tokens, spans, parse trees and diagnostics
+// implement `Serialize`/`Deserialize`, as do syntax trees and diff edits. Their JSON form, also printed by
+// `--format json` on the command line, is:
+//
+//   Span        {"start": 0, "end": 4, "line": 1, "column": 1}
+//               character offsets, 1-based line and column of `start`
+//   Token       "FUNC" for tokens without a value, {"ID": "main"},
+//               {"LIT_INT32": 5}, {"LIT_STRING": "hi"}, ... otherwise,
+//               named like the `Token` variants
+//   SpannedToken {"token": Token, "span": Span}
+//   ParseTree   {"token": Token, "node_type": "FunctionDefinition",
+//                "span": Span, "children": [ParseTree, ...]}
+//               see `NodeType` for the token and children of each node type
+//   Diagnostic  {"message": "...", "span": Span or null, "hint": "..." or null}
+//   Edit        {"kind": "Insert" | "Delete" | "Update" | "Move",
+//                "node_type": "Identifier", "old": 12 or null, "new": 14 or null,
+//                "old_span": Span or null, "new_span": Span or null,
+//                "old_label": "x" or null, "new_label": "y" or null}
+//               printed by `diff`, node ids are preorder indices in each tree
+//
+// Fields are only ever added to this schema, never renamed or removed.
+
+pub mod token;
+pub mod lexer;
+pub mod token_source;
+pub mod parse_tree;
+pub mod syntax_tree;
+pub mod tree_diff;
+pub mod parser_descent;
+pub mod observer;
+pub mod visitor;
+pub mod sexpr;
+pub mod query;
+pub mod fmt;
+pub mod highlight;
+pub mod checker;
+pub mod interpreter;
+pub mod diagnostic;
+
+pub use diagnostic::Diagnostic;
+pub use fmt::{BracketStyle, FormatOptions};
+pub use interpreter::Value;
+pub use lexer::{Comment, Lexer};
+pub use parse_tree::{NodeType, ParseTree};
+pub use observer::{ParseEvent, ParseObserver};
+pub use parser_descent::{DescentParser, ParseError};
+pub use query::{Match, Query};
+pub use syntax_tree::{Node, NodeId, SyntaxTree};
+pub use token::{Span, SpannedToken, Token};
+pub use tree_diff::{Edit, EditKind, TreeDiff};
+pub use token_source::{TokenSource, TokenStream};
+pub use visitor::{Fold, Visitor, VisitorMut};
+
+// The root `NodeType::Program` node of a parse tree
+pub type Program = ParseTree;
+
+// Every token up to EOI, with a diagnostic for each one that could not be
+// read; such a token is still in the list, e.g. an unterminated string.
+pub fn lex(source: &str) -> (Vec<SpannedToken>, Vec<Diagnostic>) {
+    let mut lexer = Lexer::new(source.to_string());
+    lexer.collect_tokens();
+    (lexer.tokens(), lexer.errors().into_iter().map(Diagnostic::from).collect())
+}
+
+pub fn parse(source: &str) -> Result<Program, Vec<Diagnostic>> {
+    let mut lexer = Lexer::new(source.to_string());
+    lexer.collect_tokens();
+    let mut parser = DescentParser::new(lexer);
+    let program = parser.analyze();
+    if parser.errors().is_empty() {
+        Ok(program)
+    } else {
+        Err(parser.errors().iter().cloned().map(Diagnostic::from).collect())
+    }
+}
+
+// The source with canonical indentation and line breaks, comments kept.
+// Only a program without syntax errors can be formatted.
+pub fn format(source: &str, options: &FormatOptions) -> Result<String, Vec<Diagnostic>> {
+    let mut lexer = Lexer::new(source.to_string());
+    lexer.collect_tokens();
+    let comments = lexer.comments();
+    let mut parser = DescentParser::new(lexer);
+    let program = parser.analyze();
+    if parser.errors().is_empty() {
+        Ok(fmt::format(&program, source, &comments, options))
+    } else {
+        Err(parser.errors().iter().cloned().map(Diagnostic::from).collect())
+    }
+}
+
+pub fn check(program: &Program) -> Vec<Diagnostic> {
+    checker::check(program)
+}
+
+pub fn run(program: &Program, out: &mut (dyn std::io::Write + Send)) -> Result<Value, Diagnostic> {
+    interpreter::run(program, out)
+}
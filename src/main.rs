@@ -1,32 +1,431 @@
 // CS 1163
 // Chris Perrone
 
-mod token;
-mod lexer;
-mod parse_tree;
-mod parser_descent;
-
-use lexer::Lexer;
-
-fn main() {
-    //let mut lexer = Lexer::new(String::from("func add(freevar : int32, y : flt32) -> int32"));
-    //lexer.print_tokens();
-
-    let mut input = Lexer::new(String::from("\
-    func add(x : int32) -> int32\
-    [\
-        let value : int32 = 35;\
-        value = value + x;\
-        return value;\
-    ]\
-    \
-    func main()\
-    [\
-        let sum : int32 = 0;\
-        sum = add(2) + 5;\
-        print \"sum=\", sum;\
-    ]"));
-    //input.print_tokens();
-
-    parser_descent::main();
+use std::fs;
+use std::io::{self, Read, Write};
+use std::process::ExitCode;
+
+use exercise3::highlight;
+use exercise3::observer::{JsonObserver, TraceObserver};
+use exercise3::{BracketStyle, EditKind, SyntaxTree, DescentParser, Diagnostic, FormatOptions, Lexer, Query, Span};
+
+const USAGE: &str = "\
+usage: Exercise3 <command> [--format FORMAT] [FILE...]
+       Exercise3 query PATTERN [FILE...]
+       Exercise3 diff [--format FORMAT] OLD NEW
+       Exercise3 highlight [--format html] [--diagnostics] [FILE...]
+       Exercise3 fmt [--check] [--width N] [--indent N] [--brackets STYLE] [FILE...]
+
+Reads every FILE, or standard input when no FILE (or '-') is given.
+
+commands:
+  tokens   print the tokens with their line:column positions
+  parse    print the parse tree
+  trace    print the recursive descent trace, then the parse tree
+  events   print the recursive descent trace as JSON lines
+  check    report syntax and semantic errors
+  run      check the program, then run its main function
+  query    print the nodes matching PATTERN and their captures, e.g.
+           '(WhileStatement !... (AssignmentStatement)) @loop', see the
+           `query` module for the pattern language
+  fmt      print the program laid out canonically, comments kept
+  diff     print the structural edits from OLD to NEW, layout and comments
+           do not count
+  highlight
+           print the source in colour for a terminal, or as an HTML page
+
+options:
+  --format FORMAT  text (default), dot for a Graphviz 'parse' tree, sexpr for
+                   a one line S-expression 'parse' tree, html for 'highlight',
+                   or json for 'tokens', 'parse', 'check' and 'diff' (needs
+                   the serde feature)
+  --check          with 'fmt': print nothing, fail for files not laid out canonically
+  --width N        with 'fmt': break lists in lines longer than N (default 80)
+  --indent N       with 'fmt': N spaces per nesting level (default 4)
+  --brackets STYLE with 'fmt': the '[' of a block on its own line (own-line,
+                   the default) or at the end of the line before (same-line)
+  --diagnostics    with 'highlight': mark the syntax and semantic errors
+
+exit status: 0 on success, 1 if a program has errors, 2 on usage or I/O errors";
+
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Text,
+    Dot,
+    Sexpr,
+    Html,
+    #[cfg(feature = "serde")]
+    Json,
+}
+
+impl Format {
+    fn supported_by(self, command: &str) -> bool {
+        match self {
+            Format::Text => true,
+            Format::Dot | Format::Sexpr => command == "parse",
+            Format::Html => command == "highlight",
+            #[cfg(feature = "serde")]
+            Format::Json => matches!(command, "tokens" | "parse" | "check" | "diff"),
+        }
+    }
+}
+
+struct Options {
+    format: Format,
+    check: bool,
+    layout: FormatOptions,
+    layout_given: bool,  // any of the 'fmt' only options
+    query: Option<Query>,
+    diagnostics: bool,
+}
+
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let Some((command, arguments)) = args.split_first() else {
+        eprintln!("{USAGE}");
+        return ExitCode::from(2);
+    };
+    let (mut options, mut files) = match parse_options(arguments) {
+        Ok(parsed) => parsed,
+        Err(message) => {
+            eprintln!("{message}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    if !options.format.supported_by(command) {
+        eprintln!("'{command}' does not support this --format\n\n{USAGE}");
+        return ExitCode::from(2);
+    }
+    if options.layout_given && command != "fmt" {
+        eprintln!("'{command}' does not take 'fmt' options\n\n{USAGE}");
+        return ExitCode::from(2);
+    }
+    if options.diagnostics && command != "highlight" {
+        eprintln!("'--diagnostics' only applies to 'highlight'\n\n{USAGE}");
+        return ExitCode::from(2);
+    }
+    if command == "query" {
+        if files.is_empty() {
+            eprintln!("'query' needs a PATTERN\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+        match Query::new(&files.remove(0)) {
+            Ok(query) => options.query = Some(query),
+            Err(error) => {
+                eprintln!("PATTERN:{error}");
+                return ExitCode::from(2);
+            }
+        }
+    }
+    if command == "diff" {
+        return diff(&options, &files);
+    }
+    let command: fn(&Options, &str, &str) -> bool = match command.as_str() {
+        "tokens" => tokens,
+        "parse" => parse,
+        "trace" => trace,
+        "events" => events,
+        "check" => check,
+        "run" => run,
+        "query" => query,
+        "fmt" => fmt,
+        "highlight" => highlight,
+        "-h" | "--help" | "help" => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        _ => {
+            eprintln!("unknown command '{command}'\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let files = if files.is_empty() { vec!["-".to_string()] } else { files };
+    let mut success = true;
+    for file in &files {
+        let source = match read_source(file) {
+            Ok(source) => source,
+            Err(error) => {
+                eprintln!("{file}: {error}");
+                return ExitCode::from(2);
+            }
+        };
+        success &= command(&options, display_name(file), &source);
+    }
+    if success { ExitCode::SUCCESS } else { ExitCode::from(1) }
+}
+
+
+// split the options from the file names
+fn parse_options(arguments: &[String]) -> Result<(Options, Vec<String>), String> {
+    let mut options = Options { format: Format::Text, check: false, layout: FormatOptions::default(), layout_given: false, query: None, diagnostics: false };
+    let mut files = vec![];
+    let mut arguments = arguments.iter();
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "--format" => {
+                options.format = match arguments.next().map(String::as_str) {
+                    Some("text") => Format::Text,
+                    Some("dot") => Format::Dot,
+                    Some("sexpr") => Format::Sexpr,
+                    Some("html") => Format::Html,
+                    #[cfg(feature = "serde")]
+                    Some("json") => Format::Json,
+                    #[cfg(not(feature = "serde"))]
+                    Some("json") => return Err("json output needs a build with '--features serde'".to_string()),
+                    Some(format) => return Err(format!("unknown format '{format}'")),
+                    None => return Err("--format needs a value".to_string()),
+                };
+            }
+            "--diagnostics" => options.diagnostics = true,
+            "--check" => {
+                options.check = true;
+                options.layout_given = true;
+            }
+            "--width" | "--indent" => {
+                let value = arguments.next().and_then(|value| value.parse::<usize>().ok())
+                    .ok_or(format!("{argument} needs a number"))?;
+                if argument == "--width" {
+                    options.layout.width = value;
+                } else {
+                    options.layout.indent = value;
+                }
+                options.layout_given = true;
+            }
+            "--brackets" => {
+                options.layout.brackets = match arguments.next().map(String::as_str) {
+                    Some("own-line") => BracketStyle::OwnLine,
+                    Some("same-line") => BracketStyle::SameLine,
+                    Some(style) => return Err(format!("unknown bracket style '{style}'")),
+                    None => return Err("--brackets needs a value".to_string()),
+                };
+                options.layout_given = true;
+            }
+            _ => files.push(argument.clone()),
+        }
+    }
+    Ok((options, files))
+}
+
+
+#[cfg_attr(not(feature = "serde"), allow(unused_variables))]
+fn tokens(options: &Options, name: &str, source: &str) -> bool {
+    let (tokens, diagnostics) = exercise3::lex(source);
+    #[cfg(feature = "serde")]
+    if options.format == Format::Json {
+        print_json(&tokens);
+        return report(name, &diagnostics);
+    }
+    for spanned in tokens {
+        println!("{}\t{:?}", spanned.span, spanned.token);
+    }
+    report(name, &diagnostics)
+}
+
+fn parse(options: &Options, name: &str, source: &str) -> bool {
+    match exercise3::parse(source) {
+        Ok(program) => {
+            match options.format {
+                Format::Text | Format::Html => program.print(),  // html is for 'highlight' only
+                Format::Dot => print!("{}", program.to_dot()),
+                Format::Sexpr => println!("{}", program.to_sexpr()),
+                #[cfg(feature = "serde")]
+                Format::Json => print_json(&program),
+            }
+            true
+        }
+        Err(diagnostics) => report(name, &diagnostics),
+    }
+}
+
+fn trace(_options: &Options, name: &str, source: &str) -> bool {
+    let mut lexer = Lexer::new(source.to_string());
+    lexer.collect_tokens();
+    let mut parser = DescentParser::new(lexer);
+    parser.set_observer(Box::new(TraceObserver::stdout()));
+    let tree = parser.analyze();
+    tree.print();
+    let diagnostics: Vec<Diagnostic> = parser.errors().iter().cloned().map(Diagnostic::from).collect();
+    report(name, &diagnostics)
+}
+
+fn events(_options: &Options, name: &str, source: &str) -> bool {
+    let mut lexer = Lexer::new(source.to_string());
+    lexer.collect_tokens();
+    let mut parser = DescentParser::new(lexer);
+    parser.set_observer(Box::new(JsonObserver::stdout()));
+    parser.analyze();
+    let diagnostics: Vec<Diagnostic> = parser.errors().iter().cloned().map(Diagnostic::from).collect();
+    report(name, &diagnostics)
+}
+
+#[cfg_attr(not(feature = "serde"), allow(unused_variables))]
+fn check(options: &Options, name: &str, source: &str) -> bool {
+    let diagnostics = match exercise3::parse(source) {
+        Ok(program) => exercise3::check(&program),
+        Err(diagnostics) => diagnostics,
+    };
+    #[cfg(feature = "serde")]
+    if options.format == Format::Json {
+        print_json(&diagnostics);
+        return diagnostics.is_empty();
+    }
+    report(name, &diagnostics)
+}
+
+fn run(_options: &Options, name: &str, source: &str) -> bool {
+    let program = match exercise3::parse(source) {
+        Ok(program) => program,
+        Err(diagnostics) => return report(name, &diagnostics),
+    };
+    if !report(name, &exercise3::check(&program)) {
+        return false;
+    }
+    let mut out = io::stdout();
+    let result = exercise3::run(&program, &mut out);
+    out.flush().ok();
+    match result {
+        Ok(_) => true,
+        Err(diagnostic) => report(name, &[diagnostic]),
+    }
+}
+
+fn query(options: &Options, name: &str, source: &str) -> bool {
+    let program = match exercise3::parse(source) {
+        Ok(program) => program,
+        Err(diagnostics) => return report(name, &diagnostics),
+    };
+    let text: Vec<char> = source.chars().collect();
+    for found in options.query.as_ref().unwrap().matches(&program) {
+        println!("{name}:{}: {:?}  {}", found.node.span(), found.node.node_type(), excerpt(&text, found.node.span()));
+        for (capture, node) in &found.captures {
+            println!("    @{capture} {}: {}", node.span(), excerpt(&text, node.span()));
+        }
+    }
+    true
+}
+
+// the source text of a span on one line, shortened when it is long
+fn excerpt(text: &[char], span: Span) -> String {
+    let end = span.end.min(text.len());
+    let source: String = text[span.start.min(end)..end].iter().collect();
+    let words = source.split_whitespace().collect::<Vec<_>>().join(" ");
+    if words.chars().count() > 60 {
+        format!("{}...", words.chars().take(57).collect::<String>())
+    } else {
+        words
+    }
+}
+
+// not one file at a time like the other commands, but a pair
+#[cfg_attr(not(feature = "serde"), allow(unused_variables))]
+fn diff(options: &Options, files: &[String]) -> ExitCode {
+    let [old_file, new_file] = files else {
+        eprintln!("'diff' needs two files, OLD and NEW\n\n{USAGE}");
+        return ExitCode::from(2);
+    };
+    let mut trees = vec![];
+    let mut sources = vec![];
+    for file in [old_file, new_file] {
+        let source = match read_source(file) {
+            Ok(source) => source,
+            Err(error) => {
+                eprintln!("{file}: {error}");
+                return ExitCode::from(2);
+            }
+        };
+        match exercise3::parse(&source) {
+            Ok(program) => trees.push(SyntaxTree::new(&program)),
+            Err(diagnostics) => {
+                report(display_name(file), &diagnostics);
+                return ExitCode::from(1);
+            }
+        }
+        sources.push(source.chars().collect::<Vec<char>>());
+    }
+    let diff = exercise3::tree_diff::diff(&trees[0], &trees[1]);
+    #[cfg(feature = "serde")]
+    if options.format == Format::Json {
+        print_json(&diff.edits);
+        return ExitCode::SUCCESS;
+    }
+    let (old, new) = (&sources[0], &sources[1]);
+    for edit in &diff.edits {
+        let (old_span, new_span) = (edit.old_span.unwrap_or_default(), edit.new_span.unwrap_or_default());
+        match edit.kind {
+            EditKind::Delete => println!("delete {:?} at {old_span}: {}", edit.node_type, excerpt(old, old_span)),
+            EditKind::Insert => println!("insert {:?} at {new_span}: {}", edit.node_type, excerpt(new, new_span)),
+            EditKind::Update => println!("update {:?} at {old_span} -> {new_span}: {} -> {}", edit.node_type,
+                edit.old_label.as_deref().unwrap_or_default(), edit.new_label.as_deref().unwrap_or_default()),
+            EditKind::Move => println!("move {:?} from {old_span} to {new_span}: {}", edit.node_type, excerpt(new, new_span)),
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+fn fmt(options: &Options, name: &str, source: &str) -> bool {
+    match exercise3::format(source, &options.layout) {
+        Ok(formatted) if options.check => {
+            if formatted != source {
+                eprintln!("{name}: not formatted");
+            }
+            formatted == source
+        }
+        Ok(formatted) => {
+            print!("{formatted}");
+            true
+        }
+        Err(diagnostics) => report(name, &diagnostics),
+    }
+}
+
+
+fn highlight(options: &Options, name: &str, source: &str) -> bool {
+    let diagnostics = match (options.diagnostics, exercise3::parse(source)) {
+        (false, _) => vec![],
+        (true, Ok(program)) => exercise3::check(&program),
+        (true, Err(diagnostics)) => diagnostics,
+    };
+    if options.format == Format::Html {
+        print!("{}", highlight::to_html_document(name, source, &diagnostics));
+        return diagnostics.is_empty();
+    }
+    print!("{}", highlight::to_ansi(source, &diagnostics));
+    report(name, &diagnostics)
+}
+
+
+// print diagnostics to stderr, returns true when there were none
+fn report(name: &str, diagnostics: &[Diagnostic]) -> bool {
+    for diagnostic in diagnostics {
+        match diagnostic.span {
+            Some(_) => eprintln!("{name}:{diagnostic}"),
+            None => eprintln!("{name}: {diagnostic}"),
+        }
+    }
+    diagnostics.is_empty()
+}
+
+#[cfg(feature = "serde")]
+fn print_json<T: serde::Serialize>(value: &T) {
+    match serde_json::to_string(value) {
+        Ok(json) => println!("{json}"),
+        Err(error) => eprintln!("cannot write JSON: {error}"),
+    }
+}
+
+fn read_source(file: &str) -> io::Result<String> {
+    if file == "-" {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source)?;
+        Ok(source)
+    } else {
+        fs::read_to_string(file)
+    }
+}
+
+fn display_name(file: &str) -> &str {
+    if file == "-" { "<stdin>" } else { file }
 }
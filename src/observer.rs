@@ -0,0 +1,283 @@
+use std::io::{self, Write};
+
+use crate::token::{Span, Token};
+
+const INDENT : usize = 2;
+
+// What the recursive descent parser is doing, step by step.
+// `Backtrack` replaces `ExitRule` for a rule abandoned because of a syntax error.
+// `Consumed` is a token the grammar requires at that point, `Accepted` one it
+// allows, like the `->` before a return type or a `,` between arguments.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseEvent {
+    EnterRule(&'static str),
+    ExitRule(&'static str),
+    Consumed(Token, Span),
+    Accepted(Token, Span),
+    Backtrack(&'static str),
+}
+
+pub trait ParseObserver {
+    fn on_event(&mut self, event: &ParseEvent);
+}
+
+
+// Prints the classic indented descent trace, with the required tokens only:
+//   parse_func()
+//     expect(FUNC)
+//     expect(ID(add))
+pub struct TraceObserver<W: Write> {
+    out: W,
+    indent: usize,
+}
+
+impl TraceObserver<io::Stdout> {
+    pub fn stdout() -> TraceObserver<io::Stdout> {
+        TraceObserver::new(io::stdout())
+    }
+}
+
+impl<W: Write> TraceObserver<W> {
+    pub fn new(out: W) -> TraceObserver<W> {
+        TraceObserver { out, indent: 0 }
+    }
+}
+
+impl<W: Write> ParseObserver for TraceObserver<W> {
+    fn on_event(&mut self, event: &ParseEvent) {
+        match event {
+            ParseEvent::EnterRule(rule) => {
+                writeln!(self.out, "{:<indent$}{rule}()", "", indent = self.indent).ok();
+                self.indent += INDENT;
+            }
+            ParseEvent::ExitRule(_) | ParseEvent::Backtrack(_) => {
+                self.indent -= INDENT;
+            }
+            ParseEvent::Accepted(..) => {}
+            ParseEvent::Consumed(Token::ID(name), _) => {
+                writeln!(self.out, "{:<indent$}expect(ID({name}))", "", indent = self.indent).ok();
+            }
+            ParseEvent::Consumed(token, _) => {
+                writeln!(self.out, "{:<indent$}expect({token:?})", "", indent = self.indent).ok();
+            }
+        }
+    }
+}
+
+
+// Writes one JSON object per event, e.g.
+//   {"event":"enter","rule":"parse_func"}
+//   {"event":"consumed","token":"FUNC","span":{"start":0,"end":4,"line":1,"column":1}}
+//   {"event":"consumed","token":{"ID":"add"},"span":{"start":5,"end":8,"line":1,"column":6}}
+// Tokens and spans have the JSON form of the `serde` feature, see lib.rs, but
+// are written without it.
+pub struct JsonObserver<W: Write> {
+    out: W,
+}
+
+impl JsonObserver<io::Stdout> {
+    pub fn stdout() -> JsonObserver<io::Stdout> {
+        JsonObserver::new(io::stdout())
+    }
+}
+
+impl<W: Write> JsonObserver<W> {
+    pub fn new(out: W) -> JsonObserver<W> {
+        JsonObserver { out }
+    }
+}
+
+impl<W: Write> ParseObserver for JsonObserver<W> {
+    fn on_event(&mut self, event: &ParseEvent) {
+        let line = match event {
+            ParseEvent::EnterRule(rule) => format!(r#"{{"event":"enter","rule":"{rule}"}}"#),
+            ParseEvent::ExitRule(rule) => format!(r#"{{"event":"exit","rule":"{rule}"}}"#),
+            ParseEvent::Backtrack(rule) => format!(r#"{{"event":"backtrack","rule":"{rule}"}}"#),
+            ParseEvent::Consumed(token, span) => token_event("consumed", token, span),
+            ParseEvent::Accepted(token, span) => token_event("accepted", token, span),
+        };
+        writeln!(self.out, "{line}").ok();
+    }
+}
+
+fn token_event(event: &str, token: &Token, span: &Span) -> String {
+    format!(
+        r#"{{"event":"{event}","token":{},"span":{{"start":{},"end":{},"line":{},"column":{}}}}}"#,
+        token_json(token), span.start, span.end, span.line, span.column
+    )
+}
+
+// a variant without a value as its name, the others as {"NAME": value}
+fn token_json(token: &Token) -> String {
+    match token {
+        Token::ID(name) => format!(r#"{{"ID":"{}"}}"#, json_escape(name)),
+        Token::LIT_INT32(value) => format!(r#"{{"LIT_INT32":{value}}}"#),
+        Token::LIT_FLT32(value) if value.is_finite() => format!(r#"{{"LIT_FLT32":{value:?}}}"#),
+        Token::LIT_FLT32(_) => r#"{"LIT_FLT32":null}"#.to_string(),
+        Token::LIT_CHAR(c) => format!(r#"{{"LIT_CHAR":"{}"}}"#, json_escape(&c.to_string())),
+        Token::LIT_STRING(text) => format!(r#"{{"LIT_STRING":"{}"}}"#, json_escape(text)),
+        token => format!(r#""{token:?}""#),
+    }
+}
+
+fn json_escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser_descent::DescentParser;
+
+    // output the test keeps a handle on after giving the observer away
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn observe(source: &str, observer: impl FnOnce(Shared) -> Box<dyn ParseObserver>) -> String {
+        let out = Shared::default();
+        let mut parser = DescentParser::new(Lexer::new(source.to_string()));
+        parser.set_observer(observer(out.clone()));
+        parser.analyze();
+        String::from_utf8(out.0.take()).unwrap()
+    }
+
+    // the trace printed by the parser before it had observers, the expression
+    // rules aside, which are gone since operators are reduced on a stack
+    #[test]
+    fn the_trace_is_the_old_one() {
+        let source = "\
+func report(x : int32, y : flt32) -> int32
+[
+    let twice : int32;
+    let = ;
+    report();
+]
+
+func main()
+[
+    let s : string;
+]
+";
+        let trace = observe(source, |out| Box::new(TraceObserver::new(out)));
+        assert_eq!(trace, "\
+analyze()
+  parse_func()
+    expect(FUNC)
+    expect(ID(report))
+    parse_parameter_list()
+      expect(PARENS_L)
+      parse_parameter()
+        expect(ID(x))
+        expect(COLON)
+        expect(TYPE_INT32)
+      parse_parameter()
+        expect(ID(y))
+        expect(COLON)
+        expect(TYPE_FLT32)
+      expect(PARENS_R)
+    expect(TYPE_INT32)
+    parse_block()
+      expect(BRACKET_L)
+      parse_statement()
+        parse_variable_declaration()
+          expect(LET)
+          expect(ID(twice))
+          expect(COLON)
+          expect(TYPE_INT32)
+          expect(SEMICOLON)
+      parse_statement()
+        parse_variable_declaration()
+          expect(LET)
+      parse_statement()
+        parse_call_statement()
+          parse_function_call()
+            expect(ID(report))
+            expect(PARENS_L)
+            expect(PARENS_R)
+          expect(SEMICOLON)
+      expect(BRACKET_R)
+  parse_func()
+    expect(FUNC)
+    expect(ID(main))
+    parse_parameter_list()
+      expect(PARENS_L)
+    parse_block()
+      expect(BRACKET_L)
+      parse_statement()
+        parse_variable_declaration()
+          expect(LET)
+          expect(ID(s))
+          expect(COLON)
+          expect(TYPE_STRING)
+          expect(SEMICOLON)
+      expect(BRACKET_R)
+  expect(EOI)
+");
+    }
+
+    #[test]
+    fn json_events_write_tokens_like_serde() {
+        let events = observe("func f() -> int32 [ return g(1, 2.5, 'c', \"a b\"); ]", |out| Box::new(JsonObserver::new(out)));
+        let lines: Vec<&str> = events.lines().collect();
+        assert_eq!(lines[0], r#"{"event":"enter","rule":"analyze"}"#);
+        let tokens: Vec<&str> = lines.iter().filter(|line| line.contains(r#""token""#)).copied().collect();
+        assert_eq!(tokens[..5], [
+            r#"{"event":"consumed","token":"FUNC","span":{"start":0,"end":4,"line":1,"column":1}}"#,
+            r#"{"event":"consumed","token":{"ID":"f"},"span":{"start":5,"end":6,"line":1,"column":6}}"#,
+            r#"{"event":"consumed","token":"PARENS_L","span":{"start":6,"end":7,"line":1,"column":7}}"#,
+            r#"{"event":"accepted","token":"PARENS_R","span":{"start":7,"end":8,"line":1,"column":8}}"#,
+            r#"{"event":"accepted","token":"ARROW_R","span":{"start":9,"end":11,"line":1,"column":10}}"#,
+        ]);
+        assert!(tokens.contains(&r#"{"event":"consumed","token":{"LIT_INT32":1},"span":{"start":29,"end":30,"line":1,"column":30}}"#));
+        assert!(tokens.contains(&r#"{"event":"consumed","token":{"LIT_FLT32":2.5},"span":{"start":32,"end":35,"line":1,"column":33}}"#));
+        assert!(tokens.contains(&r#"{"event":"consumed","token":{"LIT_CHAR":"c"},"span":{"start":37,"end":40,"line":1,"column":38}}"#));
+        assert!(tokens.contains(&r#"{"event":"consumed","token":{"LIT_STRING":"a b"},"span":{"start":42,"end":47,"line":1,"column":43}}"#));
+    }
+
+    // every line is JSON, and its tokens and spans read back as the lexer's
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_events_parse_back() {
+        let source = "func f() -> int32 [ return g(1, 2.5, 'c', \"a b\"); ]";
+        let events = observe(source, |out| Box::new(JsonObserver::new(out)));
+        let mut consumed = vec![];
+        for line in events.lines() {
+            let event: serde_json::Value = serde_json::from_str(line).unwrap();
+            if let Some(token) = event.get("token") {
+                let token: Token = serde_json::from_value(token.clone()).unwrap();
+                let span: Span = serde_json::from_value(event["span"].clone()).unwrap();
+                consumed.push((token, span));
+            }
+        }
+        let mut lexer = Lexer::new(source.to_string());
+        lexer.collect_tokens();
+        let lexed: Vec<(Token, Span)> = lexer.tokens().into_iter().map(|spanned| (spanned.token, spanned.span)).collect();
+        assert_eq!(consumed, lexed);
+    }
+}
@@ -1,16 +1,64 @@
 #![allow(dead_code)]
 
-use crate::token::Token;
+use crate::token::{Span, Token};
 
+// One variant per grammar production, with the token each node carries and
+// its children.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum NodeType {
-    FunctionDefinition,
-    AssignmentStatement,
-    // Add other node types here
+    Program,              // EOI: definitions and declarations
+    FunctionDefinition,   // ID(name): ParameterList, [Type], Block
+    StructDefinition,     // ID(name): Field*
+    Field,                // ID(name): Type
+    ParameterList,        // PARENS_L: Parameter*
+    Parameter,            // ID(name): Type
+    Type,                 // TYPE_INT32, ..., or ID(struct name)
+    ArrayType,            // LIT_INT32(size): element Type
+    Block,                // BRACKET_L: statements
+    VariableDeclaration,  // ID(name): [Type], [initial value]
+    ConstantDeclaration,  // ID(name): Type, value
+    AssignmentStatement,  // ID(name): (Index | Member)*, value
+    IfStatement,          // IF: condition, Block, [Block | IfStatement]
+    ReturnStatement,      // RETURN: value
+    PrintStatement,       // PRINT or PRINTLN: values
+    WhileStatement,       // WHILE: condition, Block
+    BreakStatement,       // BREAK
+    ContinueStatement,    // CONTINUE
+    FunctionCall,         // ID(name): arguments, also constructs a struct
+    BinaryExpression,     // operator: left, right
+    UnaryExpression,      // SUB or NOT: operand
+    Literal,              // LIT_*
+    Identifier,           // ID(name)
+    ArrayLiteral,         // BRACE_L: elements
+    IndexExpression,      // BRACE_L: array, index
+    Index,                // BRACE_L: index
+    MemberExpression,     // ID(field): struct
+    Member,               // ID(field)
+    Error,                // the token where a syntax error was found
 }
 
+impl NodeType {
+    pub const ALL: [NodeType; 29] = [
+        NodeType::Program, NodeType::FunctionDefinition, NodeType::StructDefinition, NodeType::Field,
+        NodeType::ParameterList, NodeType::Parameter, NodeType::Type, NodeType::ArrayType,
+        NodeType::Block, NodeType::VariableDeclaration, NodeType::ConstantDeclaration,
+        NodeType::AssignmentStatement, NodeType::IfStatement, NodeType::ReturnStatement,
+        NodeType::PrintStatement, NodeType::WhileStatement, NodeType::BreakStatement,
+        NodeType::ContinueStatement, NodeType::FunctionCall, NodeType::BinaryExpression,
+        NodeType::UnaryExpression, NodeType::Literal, NodeType::Identifier, NodeType::ArrayLiteral,
+        NodeType::IndexExpression, NodeType::Index, NodeType::MemberExpression, NodeType::Member,
+        NodeType::Error,
+    ];
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ParseTree {
     token: Token,
     node_type: NodeType,
+    span: Span,  // the source text of this node and all of its children
+    #[allow(clippy::vec_box)]
     children: Vec<Box<ParseTree>>,
 }
 
@@ -19,16 +67,75 @@ impl ParseTree {
         ParseTree {
             token,
             node_type,
+            span: Span::default(),
             children: vec![],
         }
     }
 
+    pub fn with_span(mut self, span: Span) -> ParseTree {
+        self.span = span;
+        self
+    }
+
+    pub fn binary(operator: Token, left: ParseTree, right: ParseTree) -> ParseTree {
+        let mut tree = ParseTree::new(operator, NodeType::BinaryExpression);
+        tree.push(left);
+        tree.push(right);
+        tree
+    }
+
+    // add a child, growing the span to cover it
     pub fn push(&mut self, tree: ParseTree) {
+        self.span = self.span.cover(tree.span);
         self.children.push(Box::new(tree));
     }
 
+    pub fn token(&self) -> &Token {
+        &self.token
+    }
+
+    pub fn node_type(&self) -> NodeType {
+        self.node_type
+    }
+
+    pub fn children(&self) -> &[Box<ParseTree>] {
+        &self.children
+    }
+
+    // for passes that rewrite the tree in place, see `visitor::VisitorMut`
+    pub fn token_mut(&mut self) -> &mut Token {
+        &mut self.token
+    }
+
+    pub fn children_mut(&mut self) -> &mut Vec<Box<ParseTree>> {
+        &mut self.children
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    // the identifier this node is named by, e.g. the function or variable name
+    pub fn name(&self) -> String {
+        match &self.token {
+            Token::ID(name) => name.clone(),
+            token => format!("{token:?}"),
+        }
+    }
+
+    // the number of nodes on the longest path down from this one, a leaf is 1
+    pub fn height(&self) -> usize {
+        let mut height = 0;
+        let mut pending = vec![(self, 1)];
+        while let Some((tree, depth)) = pending.pop() {
+            height = height.max(depth);
+            pending.extend(tree.children.iter().map(|child| (child.as_ref(), depth + 1)));
+        }
+        height
+    }
+
     pub fn node_string(&self) -> String {
-        format!("{:?}", self.token)
+        format!("{:?} {:?}", self.node_type, self.token)
     }
 
     fn print_recursively(&self, level: usize) {
@@ -36,11 +143,79 @@ impl ParseTree {
         print!("{:1$}", "", shift);
         println!("{}", self.node_string());
         for child in &self.children {
-            child.as_ref().print_recursively(level + 1);
+            child.print_recursively(level + 1);
         }
     }
 
     pub fn print(&self) {
         self.print_recursively(0);
     }
-}
\ No newline at end of file
+
+    // The tree as a Graphviz digraph, e.g. for `dot -Tpng`. Nodes are labelled
+    // with `node_string`, leaves are drawn as grey boxes so the tokens stand
+    // out from the rule nodes.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph ParseTree {\n    node [fontname=\"monospace\"];\n");
+        let mut count = 0;
+        let mut pending = vec![(self, None)];  // node and the id of its parent
+        while let Some((tree, parent)) = pending.pop() {
+            let id = count;
+            count += 1;
+            let label = dot_escape(&tree.node_string());
+            if tree.children.is_empty() {
+                dot += &format!("    n{id} [label=\"{label}\", shape=box, style=filled, fillcolor=lightgrey];\n");
+            } else {
+                dot += &format!("    n{id} [label=\"{label}\"];\n");
+            }
+            if let Some(parent) = parent {
+                dot += &format!("    n{parent} -> n{id};\n");
+            }
+            for child in tree.children.iter().rev() {
+                pending.push((child, Some(id)));
+            }
+        }
+        dot += "}\n";
+        dot
+    }
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+// Drop the children iteratively, a long chain like a + a + ... + a would
+// otherwise overflow the stack with one recursive drop per node.
+impl Drop for ParseTree {
+    fn drop(&mut self) {
+        let mut pending = std::mem::take(&mut self.children);
+        while let Some(mut tree) = pending.pop() {
+            pending.append(&mut tree.children);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dot_draws_leaves_as_boxes_and_escapes_labels() {
+        let mut print = ParseTree::new(Token::PRINT, NodeType::PrintStatement);
+        print.push(ParseTree::new(Token::LIT_STRING("say \"hi\"\\".to_string()), NodeType::Literal));
+        print.push(ParseTree::new(Token::ID("x".to_string()), NodeType::Identifier));
+        let mut block = ParseTree::new(Token::BRACKET_L, NodeType::Block);
+        block.push(print);
+        assert_eq!(block.to_dot(), r#"digraph ParseTree {
+    node [fontname="monospace"];
+    n0 [label="Block BRACKET_L"];
+    n1 [label="PrintStatement PRINT"];
+    n0 -> n1;
+    n2 [label="Literal LIT_STRING(\"say \\\"hi\\\"\\\\\")", shape=box, style=filled, fillcolor=lightgrey];
+    n1 -> n2;
+    n3 [label="Identifier ID(\"x\")", shape=box, style=filled, fillcolor=lightgrey];
+    n1 -> n3;
+}
+"#);
+    }
+}
@@ -2,443 +2,761 @@
 #![allow(non_snake_case)]
 #![allow(dead_code, unused_mut, unused_variables)]
 
+use std::fmt;
+
 use crate::lexer::Lexer;
-use crate::token::Token;
-
-const INDENT : usize = 2;
-
-
-pub fn main() {
-    let mut input = String::from("\
-    func add(x : int32) -> int32\
-    [\
-        let value : int32 = 35;\
-        value = value + x;\
-        return value;\
-    ]\
-    \
-    func main()\
-    [\
-        let sum : int32 = 0;\
-        sum = add(2) + 5;\
-        print \"sum=\", sum;\
-    ]");
-
-    // create recursive descent parser
-    let mut lexer = Lexer::new(input);
-    lexer.collect_tokens();
-    //lexer.print_tokens();
-    let mut parser = DescentParser::new(lexer);
-
-    // println!("parser.curr:  {:?}", parser.curr());
-    // parser.advance();
-    // println!("parser.curr:  {:?}", parser.curr());
-    // parser.advance();
-    // println!("parser.curr:  {:?}", parser.curr());
-
-    // start recursive descent parsing
-    parser.analyze();
+use crate::observer::{ParseEvent, ParseObserver};
+use crate::parse_tree::{NodeType, ParseTree};
+use crate::token::{Span, Token};
+use crate::token_source::TokenSource;
+
+const LITERALS: [Token; 4] = [Token::LIT_INT32(0), Token::LIT_FLT32(0.0), Token::LIT_CHAR('\0'), Token::LIT_STRING(String::new())];
+const RELATIONAL_OPERATORS: [Token; 6] = [Token::EQ, Token::NEQ, Token::LT, Token::GT, Token::NLT, Token::NGT];
+const DEFAULT_MAX_DEPTH: usize = 512;
+
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParseError {
+    pub message: String,
+    pub span: Span,
+    pub hint: Option<String>,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.span, self.message)?;
+        if let Some(hint) = &self.hint {
+            write!(f, "\n  hint: {hint}")?;
+        }
+        Ok(())
+    }
+}
+
+type ParseResult = Result<ParseTree, ParseError>;
+
+
+pub(crate) fn precedence(operator: &Token) -> u8 {
+    match operator {
+        Token::MUL | Token::DIV => 3,
+        Token::ADD | Token::SUB => 2,
+        _ => 1,  // relational operators
+    }
+}
 
+// replace the top two operands by the top operator applied to them
+fn reduce(operands: &mut Vec<ParseTree>, operators: &mut Vec<Token>) {
+    let operator = operators.pop().unwrap();
+    let right = operands.pop().unwrap();
+    let left = operands.pop().unwrap();
+    operands.push(ParseTree::binary(operator, left, right));
 }
 
 
-struct DescentParser {
-    lexer: Lexer,
-    indent: usize,
+// Reads its tokens from any `TokenSource`, by default a `Lexer`, which
+// collects its tokens first unless that has been done already.
+pub struct DescentParser<S: TokenSource = Lexer> {
+    source: S,
+    observer: Option<Box<dyn ParseObserver>>,
+    rules: Vec<(&'static str, Span)>,  // rules entered but not yet left, with where they started
+    max_depth: usize,
+    loop_depth: usize,
+    errors: Vec<ParseError>,
+    expected: Vec<Token>,     // tokens tried at `expected_at` so far
+    expected_at: usize,
 }
 
 
 #[allow(unreachable_code)]
-impl DescentParser {  // simple recursive descend parser
+impl<S: TokenSource> DescentParser<S> {  // simple recursive descend parser
 
-    fn new(lexer: Lexer) -> DescentParser {
+    pub fn new(mut source: S) -> DescentParser<S> {
+        source.begin();
+        let errors = source.errors();
         DescentParser {
-            lexer,
-            indent: 0,
+            source,
+            observer: None,
+            rules: vec![],
+            max_depth: DEFAULT_MAX_DEPTH,
+            loop_depth: 0,
+            errors,
+            expected: vec![],
+            expected_at: 0,
         }
     }
 
-    fn analyze(&mut self) {
-        self.indent_print("analyze()");
-        self.indent_increment();
-        {
-            while self.peek(Token::FUNC) {
-                self.parse_func();
+    // receive the descent trace while parsing, see `observer::TraceObserver`
+    pub fn set_observer(&mut self, observer: Box<dyn ParseObserver>) {
+        self.observer = Some(observer);
+    }
+
+    pub fn take_observer(&mut self) -> Option<Box<dyn ParseObserver>> {
+        self.observer.take()
+    }
+
+    // The deepest the descent may go before giving up with a syntax error instead
+    // of overflowing the stack. Every nested block, parenthesised expression,
+    // call or array literal goes two rules deeper, and an expression may not
+    // build a tree deeper than the rules left, one level per operator or suffix.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
+    pub fn analyze(&mut self) -> ParseTree {
+        self.enter_rule("analyze");
+        let tree = {
+            let mut program = ParseTree::new(Token::EOI, NodeType::Program);
+            let depth = self.rules.len();
+            while !self.peek(Token::EOI) {
+                let token = self.curr();
+                let start = self.source.curr_span();
+                let result = if self.peek(Token::FUNC) {
+                    self.parse_func()
+                } else if self.peek(Token::LET) {
+                    self.parse_variable_declaration()
+                } else if self.peek(Token::CONST) {
+                    self.parse_constant_declaration()
+                } else if self.peek(Token::STRUCT) {
+                    self.parse_struct()
+                } else {
+                    Err(self.error_expected())
+                };
+                match result {
+                    Ok(item) => program.push(item),
+                    Err(error) => {
+                        self.unwind(depth);
+                        self.report(error);
+                        if token == Token::LET || token == Token::CONST {
+                            self.synchronize();
+                        } else {
+                            // skip ahead to the next definition or global
+                            let mut open: usize = 0;  // brackets opened in the skipped tokens, a `let` inside is a local
+                            loop {
+                                match self.curr() {
+                                    Token::EOI => break,
+                                    Token::LET if open > 0 => {}
+                                    _ if self.at_definition() => break,
+                                    Token::BRACKET_L => open += 1,
+                                    Token::BRACKET_R => open = open.saturating_sub(1),
+                                    _ => {}
+                                }
+                                self.advance();
+                            }
+                        }
+                        let span = start.to(self.source.prev_span());
+                        program.push(ParseTree::new(token, NodeType::Error).with_span(span));
+                    }
+                }
             }
-            self.expect(Token::EOI);
-        }
-        self.indent_decrement();
+            self.expect(Token::EOI).ok();
+            program
+        };
+        let span = self.exit_rule();
+        tree.with_span(span)
     }
 
     // Function Declaration
     // func <id> ( [<id> : <type> { , <id> : <type> } ] ) [ -> <type> ] <block>
     // Example: func add(x : int32) -> int32 { ... }
-    fn parse_func(&mut self) {
-        self.indent_print("parse_func()");
-        self.indent_increment();
-        {
-            self.expect(Token::FUNC);
-            self.expect(Token::id());
-            self.parse_parameter_list();
+    fn parse_func(&mut self) -> ParseResult {
+        self.enter_rule("parse_func");
+        let tree = {
+            self.expect(Token::FUNC)?;
+            let name = self.expect(Token::id())?;
+            let mut func = ParseTree::new(name, NodeType::FunctionDefinition);
+            func.push(self.parse_parameter_list()?);
             if self.accept(Token::ARROW_R) {
-                let token = self.curr();
-                if !self.is_type(&token) {
-                    return panic!("Expected type for parameter!");
+                func.push(self.parse_type()?);
+            }
+            func.push(self.parse_block()?);
+            func
+        };
+        let span = self.exit_rule();
+        Ok(tree.with_span(span))
+    }
+
+    // Struct Definition
+    // <struct> ::= struct <id> [ <id> : <type> { , <id> : <type> } ]
+    // Example: struct Point [ x : flt32, y : flt32 ]
+    fn parse_struct(&mut self) -> ParseResult {
+        self.enter_rule("parse_struct");
+        let tree = {
+            self.expect(Token::STRUCT)?;
+            let name = self.expect(Token::id())?;
+            let mut definition = ParseTree::new(name, NodeType::StructDefinition);
+            self.expect(Token::BRACKET_L)?;
+            loop {
+                let field = self.expect(Token::id())?;
+                let mut field = ParseTree::new(field, NodeType::Field).with_span(self.source.prev_span());
+                self.expect(Token::COLON)?;
+                field.push(self.parse_type()?);
+                definition.push(field);
+                if !self.accept(Token::COMMA) {
+                    break;
                 }
-                self.expect(token);
             }
-            self.parse_block_nest();
-        }
-        self.indent_decrement();
+            self.expect(Token::BRACKET_R)?;
+            definition
+        };
+        let span = self.exit_rule();
+        Ok(tree.with_span(span))
     }
 
     // Parameter List
     // <parameter-list> ::= ( [<id> : <type> { , <id> : <type> } ] )
     // Example: (x : int32, y : float32)
-    fn parse_parameter_list(&mut self) {
-        self.indent_print("parse_parameter_list()");
-        self.indent_increment();
-        {
-            self.expect(Token::PARENS_L);
-            if self.accept(Token::PARENS_R) {
-                return;
-            }
-            self.parse_parameter();
-            while self.accept(Token::COMMA) {
-                self.parse_parameter();
+    fn parse_parameter_list(&mut self) -> ParseResult {
+        self.enter_rule("parse_parameter_list");
+        let tree = {
+            let token = self.expect(Token::PARENS_L)?;
+            let mut parameters = ParseTree::new(token, NodeType::ParameterList);
+            if !self.accept(Token::PARENS_R) {
+                parameters.push(self.parse_parameter()?);
+                while self.accept(Token::COMMA) {
+                    parameters.push(self.parse_parameter()?);
+                }
+                self.expect(Token::PARENS_R)?;
             }
-            self.expect(Token::PARENS_R);
-        }
-        self.indent_decrement();
+            parameters
+        };
+        let span = self.exit_rule();
+        Ok(tree.with_span(span))
     }
 
-    fn is_type(&self, token: &Token) -> bool {
-        match token {
-            Token::TYPE_INT32 | Token::TYPE_FLT32 | Token::TYPE_CHAR | Token::TYPE_STRING | Token::TYPE_BOOL => true,
-            _ => false,
+    // <type> ::= ( <base-type> | <id> ) { { <int32-literal> } }
+    // Example: int32, flt32{3}, char{2}{8}, Point
+    fn parse_type(&mut self) -> ParseResult {
+        let token = self.curr();
+        if !self.peek_any(&Token::TYPES) && !self.peek(Token::id()) {
+            return Err(self.error_expected());
+        }
+        let mut tree = ParseTree::new(self.expect(token)?, NodeType::Type).with_span(self.source.prev_span());
+        let start = tree.span();
+        let mut sizes = vec![];
+        while self.accept(Token::BRACE_L) {
+            // every size nests the type one level deeper
+            if self.rules.len() + sizes.len() >= self.max_depth {
+                return Err(self.error_too_deep());
+            }
+            sizes.push(self.expect(Token::lit_int32())?);
+            self.expect(Token::BRACE_R)?;
+        }
+        // the first size is the outermost array: int32{2}{3} is two arrays of three
+        for size in sizes.into_iter().rev() {
+            let mut array = ParseTree::new(size, NodeType::ArrayType);
+            array.push(tree);
+            tree = array.with_span(start.to(self.source.prev_span()));
         }
+        Ok(tree)
     }
 
     // Parameter
     // <parameter> ::= <id> : <type>
     // Example: x : int32
-    fn parse_parameter(&mut self) {
-        self.indent_print("parse_parameter()");
-        self.indent_increment();
-        {
-            self.expect(Token::id());
-            self.expect(Token::COLON);
-            let token = self.curr();
-            if !self.is_type(&token) {
-                return panic!("Expected type for parameter!");
-            }
-            self.expect(token);
-        }
-        self.indent_decrement();
-    }
-
-    // Block Nest
-    // <block-nest> ::= { <block-list> }
-    // Example: { let a = 5; let b = 7; }
-    fn parse_block_nest(&mut self) {
-        self.indent_print("parse_block_nest()");
-        self.indent_increment();
-        {
-            self.expect(Token::BRACKET_L);
-            while self.peek(Token::BRACKET_L) {
-                self.parse_block_nest();
-            }
-            self.parse_block_list();
-            while self.peek(Token::RETURN) {
-                self.parse_return_statement();
-            }
+    fn parse_parameter(&mut self) -> ParseResult {
+        self.enter_rule("parse_parameter");
+        let tree = {
+            let name = self.expect(Token::id())?;
+            let mut parameter = ParseTree::new(name, NodeType::Parameter);
+            self.expect(Token::COLON)?;
+            parameter.push(self.parse_type()?);
+            parameter
+        };
+        let span = self.exit_rule();
+        Ok(tree.with_span(span))
+    }
 
-            // Add support for PRINT statement
-            while self.peek(Token::PRINT) {
-                self.parse_print_statement();
+    // Block
+    // <block> ::= [ { <statement> } ]
+    // Example: [ let a : int32 = 5; print a; [ a = 7; ] return a; ]
+    fn parse_block(&mut self) -> ParseResult {
+        self.enter_rule("parse_block");
+        let tree = {
+            let token = self.expect(Token::BRACKET_L)?;
+            let mut block = ParseTree::new(token, NodeType::Block);
+            while !self.peek(Token::BRACKET_R) && self.curr() != Token::EOI && !self.at_definition() {
+                block.push(self.parse_statement());
             }
-
-            self.expect(Token::BRACKET_R);  // This line expects a closing bracket
-        }
-        self.indent_decrement();
+            self.expect(Token::BRACKET_R)?;
+            block
+        };
+        let span = self.exit_rule();
+        Ok(tree.with_span(span))
     }
 
     // Return Statement
     // <return-statement> ::= return <expression> ;
     // Example: return value;
-    fn parse_return_statement(&mut self) {
-        self.indent_print("parse_return_statement()");
-        self.indent_increment();
-        {
-            self.expect(Token::RETURN);
-            self.parse_expression();
-            self.expect(Token::SEMICOLON);
-        }
-        self.indent_decrement();
+    fn parse_return_statement(&mut self) -> ParseResult {
+        self.enter_rule("parse_return_statement");
+        let tree = {
+            let token = self.expect(Token::RETURN)?;
+            let mut statement = ParseTree::new(token, NodeType::ReturnStatement);
+            statement.push(self.parse_expression()?);
+            self.expect(Token::SEMICOLON)?;
+            statement
+        };
+        let span = self.exit_rule();
+        Ok(tree.with_span(span))
     }
 
-
-    fn parse_variable_initial_value(&mut self) {
-        // Assuming initial values can be integers, floats, chars, strings, or bools
-        let token = self.curr();
-        match token {
-            Token::TYPE_INT32 | Token::TYPE_FLT32 | Token::TYPE_CHAR | Token::TYPE_STRING | Token::TYPE_BOOL => {
-                self.expect(token);
+    // Variable Declaration
+    // <variable-declaration> ::= let <id> [ : <type> ] [ = <expression> ] ;
+    // Example: let value : int32 = a + 35; let x = 3; let y : int32;
+    fn parse_variable_declaration(&mut self) -> ParseResult {
+        self.enter_rule("parse_variable_declaration");
+        let tree = {
+            self.expect(Token::LET)?;
+            let name = self.expect(Token::id())?;
+            let mut declaration = ParseTree::new(name, NodeType::VariableDeclaration);
+            if self.peek(Token::COLON) {
+                self.expect(Token::COLON)?;
+                declaration.push(self.parse_type()?);
             }
-            _ => {
-                return panic!("Expected integer, float, char, string, or bool for variable initial value!");
+            if self.peek(Token::ASSIGN) {
+                self.expect(Token::ASSIGN)?;
+                declaration.push(self.parse_expression()?);
             }
-        }
-    }
-
-    fn parse_function_arguments(&mut self) {
-        if self.accept(Token::PARENS_R) {
-            return;
-        }
-        while !self.peek(Token::PARENS_R) {
-            let token = self.curr();
-            if !self.is_initial_value(&token) {
-                return panic!("Expected integer, float, char, string, or bool for function argument!");
+            if declaration.children().is_empty() {
+                return Err(self.error_expected());
             }
-            self.expect(token);
-            if !self.peek(Token::PARENS_R) {
-                self.expect(Token::COMMA);
-            }
-        }
-        self.expect(Token::PARENS_R);
+            self.expect(Token::SEMICOLON)?;
+            declaration
+        };
+        let span = self.exit_rule();
+        Ok(tree.with_span(span))
     }
 
-    // Variable Declaration
-    // <variable-declaration> ::= let <id> : <type> = <initial-value> ;
-    // Example: let value : int32 = 35;
-    fn parse_variable_declaration(&mut self) {
-        self.indent_print("parse_variable_declaration()");
-        self.indent_increment();
-        {
-            self.expect(Token::LET);
-            self.expect(Token::id());
-            self.expect(Token::COLON);
-            let token = self.curr();
-            if !self.is_type(&token) {
-                return panic!("Expected type for variable!");
-            }
-            self.expect(token);
-            self.expect(Token::ASSIGN);
-            let initial_value_token = self.curr();
-            if !self.is_initial_value(&initial_value_token) {
-                return panic!("Expected integer, float, char, string, or bool for variable initial value!");
-            }
-            self.expect(initial_value_token);
-            self.expect(Token::SEMICOLON);
-        }
-        self.indent_decrement();
+    // Constant Declaration
+    // <constant-declaration> ::= const <id> : <type> = <expression> ;
+    // Example: const LIMIT : int32 = 10 * 4;
+    fn parse_constant_declaration(&mut self) -> ParseResult {
+        self.enter_rule("parse_constant_declaration");
+        let tree = {
+            self.expect(Token::CONST)?;
+            let name = self.expect(Token::id())?;
+            let mut declaration = ParseTree::new(name, NodeType::ConstantDeclaration);
+            self.expect(Token::COLON)?;
+            declaration.push(self.parse_type()?);
+            self.expect(Token::ASSIGN)?;
+            declaration.push(self.parse_expression()?);
+            self.expect(Token::SEMICOLON)?;
+            declaration
+        };
+        let span = self.exit_rule();
+        Ok(tree.with_span(span))
     }
 
     // Expression
-    // <expression> ::= <variable-access> | <lit-string> | <addition-expression>
-    // Example: x, "Hello", x + y
-    fn parse_expression(&mut self) {
-        self.indent_print("parse_expression()");
-        self.indent_increment();
-        {
-            if self.peek(Token::id()) {
-                if self.peek_next(Token::PARENS_L) {
-                    self.parse_function_call();
-                } else {
-                    self.parse_variable_access();
+    // <expression> ::= <addition-expression> [ <relational-operator> <addition-expression> ]
+    // <addition-expression> ::= <multiplication-expression> { (+ | -) <multiplication-expression> }
+    // <multiplication-expression> ::= <unary-expression> { (* | /) <unary-expression> }
+    // Example: x, x + y * 2, sum > 0
+    // The operators are reduced on an explicit stack by precedence, so a long
+    // chain like a + a + ... + a never deepens the recursion. The tree it builds
+    // is still one level deeper per operator, and the checker and printers
+    // recurse over it, so the outermost expression counts its height against
+    // the depth and is reported as too long when it does not fit.
+    fn parse_expression(&mut self) -> ParseResult {
+        self.enter_rule("parse_expression");
+        let tree = {
+            let mut operands = vec![self.parse_unary_expression()?];
+            let mut operators: Vec<Token> = vec![];
+            let mut relational = false;
+            loop {
+                let token = self.curr();
+                let arithmetic = self.peek(Token::ADD) || self.peek(Token::SUB)
+                    || self.peek(Token::MUL) || self.peek(Token::DIV);
+                if !arithmetic {
+                    // at most one comparison, `a < b < c` is an error
+                    if relational || !self.peek_any(&RELATIONAL_OPERATORS) {
+                        break;
+                    }
+                    relational = true;
                 }
-            } else if self.peek(Token::lit_string()) {
-                self.expect(Token::lit_string());
-                self.expect(Token::SEMICOLON);
-            } else {
-                self.parse_addition_expression();
+                while operators.last().is_some_and(|top| precedence(top) >= precedence(&token)) {
+                    reduce(&mut operands, &mut operators);
+                }
+                operators.push(self.expect(token)?);
+                operands.push(self.parse_unary_expression()?);
             }
-        }
-        self.indent_decrement();
-    }
-
-    // Variable Access
-    // <variable-access> ::= <id> { + <expression> }
-    // Example: x, x + 2, x + y
-    fn parse_variable_access(&mut self) {
-        self.indent_print("parse_variable_access()");
-        self.indent_increment();
-        {
-            self.expect(Token::id());
-            while self.peek(Token::ADD) {
-                self.expect(Token::ADD);
-                self.parse_expression();
+            while !operators.is_empty() {
+                reduce(&mut operands, &mut operators);
             }
+            operands.pop().unwrap()
+        };
+        let span = self.exit_rule();
+        let outermost = !self.rules.iter().any(|(rule, _)| *rule == "parse_expression");
+        if outermost && self.rules.len() + tree.height() > self.max_depth {
+            return Err(ParseError {
+                message: format!("expression too long, at most {} operators fit here", self.max_depth - self.rules.len()),
+                span,
+                hint: Some("split it up, e.g. into partial results in variables of their own".to_string()),
+            });
         }
-        self.indent_decrement();
+        Ok(tree.with_span(span))
     }
 
     // Function Call
     // <function-call> ::= <id> ( [<expression> { , <expression> } ] )
-    // Example: add(2), func()
-    fn parse_function_call(&mut self) {
-        self.indent_print("parse_function_call()");
-        self.indent_increment();
-        {
-            self.expect(Token::id());
-            self.expect(Token::PARENS_L);
-            while !self.peek(Token::PARENS_R) {
-                let token = self.curr();
-                self.expect(token);
-                if !self.peek(Token::PARENS_R) {
-                    self.expect(Token::COMMA);
+    // Example: add(2), f(a + 1, g(b), "s"), log()
+    fn parse_function_call(&mut self) -> ParseResult {
+        self.enter_rule("parse_function_call");
+        let tree = {
+            let name = self.expect(Token::id())?;
+            let mut call = ParseTree::new(name, NodeType::FunctionCall);
+            self.expect(Token::PARENS_L)?;
+            if !self.peek(Token::PARENS_R) {
+                call.push(self.parse_expression()?);
+                while self.peek(Token::COMMA) {
+                    self.expect(Token::COMMA)?;
+                    call.push(self.parse_expression()?);
                 }
             }
-            self.expect(Token::PARENS_R);
-        }
-        self.indent_decrement();
+            self.expect(Token::PARENS_R)?;
+            call
+        };
+        let span = self.exit_rule();
+        Ok(tree.with_span(span))
+    }
+
+    // Call Statement
+    // <call-statement> ::= <function-call> ;
+    // Example: log(x);
+    fn parse_call_statement(&mut self) -> ParseResult {
+        self.enter_rule("parse_call_statement");
+        let tree = {
+            let call = self.parse_function_call()?;
+            self.expect(Token::SEMICOLON)?;
+            call
+        };
+        let span = self.exit_rule();
+        Ok(tree.with_span(span))
     }
 
     // Assignment
-    // <assignment> ::= <id> = <addition-expression> ;
-    // Example: x = 5 + y;
-    fn parse_assignment(&mut self) {
-        self.indent_print("parse_assignment()");
-        self.indent_increment();
-        {
-            self.expect(Token::id());
-            self.expect(Token::ASSIGN);
-            self.parse_addition_expression();
-            self.expect(Token::SEMICOLON);
-        }
-        self.indent_decrement();
+    // <assignment> ::= <id> { { <expression> } | . <id> } = <expression> ;
+    // Example: x = 5 + y; grid{i}{0} = 1; line.end.x = 2.5;
+    fn parse_assignment(&mut self) -> ParseResult {
+        self.enter_rule("parse_assignment");
+        let tree = {
+            let name = self.expect(Token::id())?;
+            let mut assignment = ParseTree::new(name, NodeType::AssignmentStatement);
+            loop {
+                if self.peek(Token::BRACE_L) {
+                    let token = self.expect(Token::BRACE_L)?;
+                    let start = self.source.prev_span();
+                    let mut index = ParseTree::new(token, NodeType::Index);
+                    index.push(self.parse_expression()?);
+                    self.expect(Token::BRACE_R)?;
+                    assignment.push(index.with_span(start.to(self.source.prev_span())));
+                } else if self.accept(Token::POINT) {
+                    let start = self.source.prev_span();  // the '.'
+                    let field = self.expect(Token::id())?;
+                    assignment.push(ParseTree::new(field, NodeType::Member).with_span(start.to(self.source.prev_span())));
+                } else {
+                    break;
+                }
+            }
+            self.expect(Token::ASSIGN)?;
+            assignment.push(self.parse_expression()?);
+            self.expect(Token::SEMICOLON)?;
+            assignment
+        };
+        let span = self.exit_rule();
+        Ok(tree.with_span(span))
     }
 
-    fn is_specific_int_literal(&mut self, value: i32) -> bool {
-        if let Token::LIT_INT32(v) = self.curr() {
-            v == value
-        } else {
-            false
+    // <unary-expression> ::= { - | ! } <primary-expression>
+    // Example: -x, !done, - -5
+    // Like `parse_type` this is not traced as a rule of its own.
+    fn parse_unary_expression(&mut self) -> ParseResult {
+        let mut operators = vec![];
+        loop {
+            let token = self.curr();
+            if !self.peek(Token::SUB) && !self.peek(Token::NOT) {
+                break;
+            }
+            let operator = self.expect(token)?;
+            operators.push((operator, self.source.prev_span()));
+        }
+        let mut expression = self.parse_primary_expression()?;
+        while let Some((operator, span)) = operators.pop() {
+            let mut unary = ParseTree::new(operator, NodeType::UnaryExpression).with_span(span);
+            unary.push(expression);
+            expression = unary;
         }
+        Ok(expression)
     }
 
-    // Addition Expression
-    // <addition-expression> ::= <term> { + <term> }
-    // Example: x + y, 5 + 3
-    fn parse_addition_expression(&mut self) {
-        self.indent_print("parse_addition_expression()");
-        self.indent_increment();
-        {
-            let current_token = self.curr();
-            if self.is_specific_int_literal(5) {
-                self.expect(current_token);
+    // Primary Expression
+    // <primary-expression> ::= <literal> | ( <function-call> | <id> ) { { <expression> } | . <id> }
+    //                          | <array-literal> | ( <expression> )
+    // Example: 5, "Hello", add(2), x, values{i + 1}, p.x, Point(1.0, 2.0).y, {1, 2}, (x + 1)
+    fn parse_primary_expression(&mut self) -> ParseResult {
+        self.enter_rule("parse_primary_expression");
+        let tree = {
+            let token = self.curr();
+            if self.rules.len() > self.max_depth {
+                return Err(self.error_too_deep());
+            } else if self.peek_any(&LITERALS) {
+                ParseTree::new(self.expect(token)?, NodeType::Literal).with_span(self.source.prev_span())
             } else if self.peek(Token::id()) {
-                self.parse_expression();
+                let mut expression = if self.peek_next(Token::PARENS_L) {
+                    self.parse_function_call()?
+                } else {
+                    ParseTree::new(self.expect(Token::id())?, NodeType::Identifier).with_span(self.source.prev_span())
+                };
+                loop {
+                    if self.peek(Token::BRACE_L) {
+                        let token = self.expect(Token::BRACE_L)?;
+                        let start = expression.span();
+                        let mut index = ParseTree::new(token, NodeType::IndexExpression);
+                        index.push(expression);
+                        index.push(self.parse_expression()?);
+                        self.expect(Token::BRACE_R)?;
+                        expression = index.with_span(start.to(self.source.prev_span()));
+                    } else if self.accept(Token::POINT) {
+                        let field = self.expect(Token::id())?;
+                        let start = expression.span();
+                        let mut member = ParseTree::new(field, NodeType::MemberExpression);
+                        member.push(expression);
+                        expression = member.with_span(start.to(self.source.prev_span()));
+                    } else {
+                        break;
+                    }
+                }
+                expression
+            } else if self.peek(Token::BRACE_L) {
+                self.parse_array_literal()?
+            } else if self.accept(Token::PARENS_L) {
+                let expression = self.parse_expression()?;
+                self.expect(Token::PARENS_R)?;
+                expression
             } else {
-                // Handle other types of expressions
+                return Err(self.error_expected());
             }
+        };
+        let span = self.exit_rule();
+        Ok(tree.with_span(span))
+    }
 
-            while self.peek(Token::ADD) {
-                self.expect(Token::ADD);
-                let current_token = self.curr();
-                if self.is_specific_int_literal(5) {
-                    self.expect(current_token);
-                } else if self.peek(Token::id()) {
-                    self.parse_expression();
-                } else {
-                    // Handle other types of expressions
-                }
+    // Array Literal
+    // <array-literal> ::= { <expression> { , <expression> } }
+    // Example: {1, 2, 3}, {{1, 0}, {0, 1}}
+    fn parse_array_literal(&mut self) -> ParseResult {
+        self.enter_rule("parse_array_literal");
+        let tree = {
+            let token = self.expect(Token::BRACE_L)?;
+            let mut array = ParseTree::new(token, NodeType::ArrayLiteral);
+            array.push(self.parse_expression()?);
+            while self.accept(Token::COMMA) {
+                array.push(self.parse_expression()?);
             }
-        }
-        self.indent_decrement();
+            self.expect(Token::BRACE_R)?;
+            array
+        };
+        let span = self.exit_rule();
+        Ok(tree.with_span(span))
     }
 
     // Statement
-    // <statement> ::= <variable-declaration> | <assignment> | <print-statement> | <other-statements>
-    // Example: let x = 5; x = 10; print "Hello"; if (x > 0) { ... }
-    fn parse_statement(&mut self) {
-        self.indent_print("parse_statement()");
-        self.indent_increment();
-        {
-            if self.peek(Token::LET) {
-                self.parse_variable_declaration();
+    // <statement> ::= <block> | <variable-declaration> | <assignment> | <call-statement>
+    //               | <return-statement> | <print-statement> | <if-statement> | <while-statement>
+    //               | <break-statement> | <continue-statement>
+    // Example: let x : int32 = 5; x = 10; log(x); print "Hello"; return x; [ ... ]
+    fn parse_statement(&mut self) -> ParseTree {
+        self.enter_rule("parse_statement");
+        let depth = self.rules.len();
+        let token = self.curr();
+        let result = {
+            if self.rules.len() > self.max_depth {
+                Err(self.error_too_deep())
+            } else if self.peek(Token::BRACKET_L) {
+                self.parse_block()
+            } else if self.peek(Token::LET) {
+                self.parse_variable_declaration()
             } else if self.peek(Token::id()) {
-                self.parse_assignment();
-            } else if self.peek(Token::PRINT) {
-                self.parse_print_statement();  // Add this function for handling PRINT
+                if self.peek_next(Token::PARENS_L) {
+                    self.parse_call_statement()
+                } else {
+                    self.parse_assignment()
+                }
+            } else if self.peek(Token::RETURN) {
+                self.parse_return_statement()
+            } else if self.peek(Token::PRINT) || self.peek(Token::PRINTLN) {
+                self.parse_print_statement()
+            } else if self.peek(Token::IF) {
+                self.parse_if_statement()
+            } else if self.peek(Token::WHILE) {
+                self.parse_while_statement()
+            } else if self.peek(Token::BREAK) {
+                self.parse_break_statement()
+            } else if self.peek(Token::CONTINUE) {
+                self.parse_continue_statement()
             } else {
-                // Handle other types of statements
+                Err(self.error_expected())
             }
-        }
-        self.indent_decrement();
+        };
+        let tree = match result {
+            Ok(statement) => statement,
+            Err(error) => {
+                self.unwind(depth);
+                let tree = ParseTree::new(token, NodeType::Error);
+                self.report(error);
+                self.synchronize();
+                tree
+            }
+        };
+        let span = self.exit_rule();
+        tree.with_span(span)
     }
 
-    // Print Statement
-    // <print-statement> ::= print <lit-string> , <expression> ;
-    // Example: print "sum=", sum;
-    fn parse_print_statement(&mut self) {
-        self.indent_print("parse_print_statement()");
-        self.indent_increment();
-        {
-            self.expect(Token::PRINT);
-            self.expect(Token::LIT_STRING("sum=".to_string()));
-            self.expect(Token::COMMA);
-            self.expect(Token::ID("sum".to_string()));
-            self.expect(Token::SEMICOLON);
-        }
-        self.indent_decrement();
-    }
-
-    // Block List
-    // <block-list> ::= { [<block-nest>] <statement> { <statement> } }
-    // Example: { let x = 5; if (x > 0) { ... } }
-    fn parse_block_list(&mut self) {
-        self.indent_print("parse_block_list()");
-        self.indent_increment();
-        {
-            while self.peek(Token::BRACKET_L) && self.peek_next(Token::BRACKET_L) {
-                self.parse_block_nest();
+    // If Statement
+    // <if-statement> ::= if <expression> [ then ] <block> [ else ( <block> | <if-statement> ) ]
+    // Example: if x > 0 [ print x; ] else if x == 0 [ print 0; ] else [ print -x; ]
+    fn parse_if_statement(&mut self) -> ParseResult {
+        self.enter_rule("parse_if_statement");
+        let tree = {
+            let token = self.expect(Token::IF)?;
+            let mut statement = ParseTree::new(token, NodeType::IfStatement);
+            statement.push(self.parse_expression()?);
+            self.accept(Token::THEN);
+            statement.push(self.parse_block()?);
+            if self.accept(Token::ELSE) {
+                if self.peek(Token::IF) {
+                    statement.push(self.parse_if_statement()?);
+                } else {
+                    statement.push(self.parse_block()?);
+                }
+            }
+            statement
+        };
+        let span = self.exit_rule();
+        Ok(tree.with_span(span))
+    }
+
+    // While Statement
+    // <while-statement> ::= while <expression> <block>
+    // Example: while i < 10 [ i = i + 1; ]
+    fn parse_while_statement(&mut self) -> ParseResult {
+        self.enter_rule("parse_while_statement");
+        let tree = {
+            let token = self.expect(Token::WHILE)?;
+            let mut statement = ParseTree::new(token, NodeType::WhileStatement);
+            statement.push(self.parse_expression()?);
+            self.loop_depth += 1;
+            let body = self.parse_block();
+            self.loop_depth -= 1;
+            statement.push(body?);
+            statement
+        };
+        let span = self.exit_rule();
+        Ok(tree.with_span(span))
+    }
+
+    // Break Statement
+    // <break-statement> ::= break ;
+    // Example: break;
+    fn parse_break_statement(&mut self) -> ParseResult {
+        self.enter_rule("parse_break_statement");
+        let tree = {
+            if self.loop_depth == 0 {
+                let error = self.error_here("'break' is only allowed inside a while loop!".to_string());
+                self.report(error);
+            }
+            let token = self.expect(Token::BREAK)?;
+            self.expect(Token::SEMICOLON)?;
+            ParseTree::new(token, NodeType::BreakStatement)
+        };
+        let span = self.exit_rule();
+        Ok(tree.with_span(span))
+    }
+
+    // Continue Statement
+    // <continue-statement> ::= continue ;
+    // Example: continue;
+    fn parse_continue_statement(&mut self) -> ParseResult {
+        self.enter_rule("parse_continue_statement");
+        let tree = {
+            if self.loop_depth == 0 {
+                let error = self.error_here("'continue' is only allowed inside a while loop!".to_string());
+                self.report(error);
             }
-            while self.peek(Token::LET) || self.peek(Token::id()) {
-                self.parse_statement();
+            let token = self.expect(Token::CONTINUE)?;
+            self.expect(Token::SEMICOLON)?;
+            ParseTree::new(token, NodeType::ContinueStatement)
+        };
+        let span = self.exit_rule();
+        Ok(tree.with_span(span))
+    }
+
+    // Print Statement
+    // <print-statement> ::= print <expression> { , <expression> } ;
+    //                     | println [ <expression> { , <expression> } ] ;
+    // Example: print "x=", x, " y=", y * 2;
+    fn parse_print_statement(&mut self) -> ParseResult {
+        self.enter_rule("parse_print_statement");
+        let tree = {
+            let mut statement;
+            if self.peek(Token::PRINTLN) {
+                let token = self.expect(Token::PRINTLN)?;
+                statement = ParseTree::new(token, NodeType::PrintStatement);
+                if !self.peek(Token::SEMICOLON) {
+                    self.parse_print_list(&mut statement)?;
+                }
+            } else {
+                let token = self.expect(Token::PRINT)?;
+                statement = ParseTree::new(token, NodeType::PrintStatement);
+                self.parse_print_list(&mut statement)?;
             }
+            self.expect(Token::SEMICOLON)?;
+            statement
+        };
+        let span = self.exit_rule();
+        Ok(tree.with_span(span))
+    }
+
+    fn parse_print_list(&mut self, statement: &mut ParseTree) -> Result<(), ParseError> {
+        statement.push(self.parse_expression()?);
+        while self.peek(Token::COMMA) {
+            self.expect(Token::COMMA)?;
+            statement.push(self.parse_expression()?);
         }
-        self.indent_decrement();
+        Ok(())
     }
 }
 
 
-impl DescentParser { // utility functions for lexer
+impl<S: TokenSource> DescentParser<S> { // utility functions for the token source
 
     fn curr(&mut self) -> Token {
-        self.lexer.curr1()
+        self.source.current()
     }
 
     fn advance(&mut self) {
-        self.lexer.advance1();
+        self.source.bump();
     }
 
-    fn expect(&mut self, symbol: Token) {
-        if self.curr() == symbol {
-            match symbol {
-                Token::ID(_) => {
-                    if let Token::ID(name) = self.curr() {
-                        println!("{:<indent$}expect(ID({}))", "", name, indent = self.indent);
-                    }
-                }
-                _ => {
-                    println!("{:<indent$}expect({symbol:?})", "", indent = self.indent);
-                }
-            }
-            self.advance();
+    fn expect(&mut self, symbol: Token) -> Result<Token, ParseError> {
+        self.note_expected(&symbol);
+        let token = self.curr();
+        if token == symbol {
+            self.consume(ParseEvent::Consumed);
+            Ok(token)
         } else {
-            println!(
-                "Error: Expected {:?}, but got {:?}",
-                symbol,
-                self.curr()
-            );
-            panic!("Did not expect '{symbol:?}'!");
+            Err(self.error_expected())
         }
     }
 
     fn accept(&mut self, symbol: Token) -> bool {
+        self.note_expected(&symbol);
         if self.curr() == symbol {
-            self.advance();
+            self.consume(ParseEvent::Accepted);
             true
         } else {
             false
@@ -446,33 +764,410 @@ impl DescentParser { // utility functions for lexer
     }
 
     fn peek(&mut self, symbol: Token) -> bool {
-        self.lexer.curr1() == symbol
+        self.note_expected(&symbol);
+        self.source.current() == symbol
+    }
+
+    fn peek_any(&mut self, symbols: &[Token]) -> bool {
+        let mut found = false;
+        for symbol in symbols {
+            found |= self.peek(symbol.clone());
+        }
+        found
     }
 
     fn peek_next(&mut self, symbol: Token) -> bool {
-        self.lexer.peek_next() == symbol
+        self.source.peek_next() == symbol
     }
 
-    fn is_initial_value(&self, token: &Token) -> bool {
-        match token {
-            Token::LIT_INT32(_) | Token::LIT_FLT32(_) | Token::LIT_CHAR(_) | Token::LIT_STRING(_) => true,
+    // at a keyword that only starts a top level definition, or at a `let`
+    // outside of the functions, which declares a global
+    fn at_definition(&mut self) -> bool {
+        match self.curr() {
+            Token::FUNC | Token::CONST | Token::STRUCT => true,
+            Token::LET => !self.rules.iter().any(|(rule, _)| *rule == "parse_func"),
             _ => false,
         }
     }
 }
 
 
-impl DescentParser { // utility functions for pretty print
+impl<S: TokenSource> DescentParser<S> { // utility functions for error recovery
+
+    // Record a syntax error, unless one is already recorded at the same place:
+    // after a statement fails at the end of the input, the rules around it fail
+    // there too, only expecting a token or two more
+    fn report(&mut self, error: ParseError) {
+        if self.errors.last().map(|last| last.span) != Some(error.span) {
+            self.errors.push(error);
+        }
+    }
+
+    fn error_here(&self, message: String) -> ParseError {
+        ParseError {
+            message,
+            span: self.source.curr_span(),
+            hint: None,
+        }
+    }
+
+    fn error_too_deep(&self) -> ParseError {
+        self.error_here(format!("nested too deeply, the parser gives up at a depth of {}", self.max_depth))
+    }
+
+    // Remember every token the grammar was prepared to accept at the
+    // current position, so a failure can list all of the alternatives.
+    fn note_expected(&mut self, symbol: &Token) {
+        let position = self.source.position();
+        if position != self.expected_at {
+            self.expected.clear();
+            self.expected_at = position;
+        }
+        if !self.expected.contains(symbol) {
+            self.expected.push(symbol.clone());
+        }
+    }
 
-    fn indent_print(&mut self, msg: &'static str) {
-        println!("{:<indent$}{:}", "", msg, indent=self.indent);
+    fn error_expected(&mut self) -> ParseError {
+        let found = self.curr();
+        if self.expected_at != self.source.position() {
+            self.expected.clear();
+        }
+        let names: Vec<String> = self.expected.iter().map(Token::describe).collect();
+        let message = match names.as_slice() {
+            [] => format!("Unexpected {}", found.describe_found()),
+            [name] => format!("Expected {}, but got {}", name, found.describe_found()),
+            [rest @ .., last] => format!("Expected one of {} or {}, but got {}", rest.join(", "), last, found.describe_found()),
+        };
+        let mut error = self.error_here(message);
+        error.hint = self.hint(&found);
+        error
     }
 
-    fn indent_increment(&mut self) {
-        self.indent += INDENT;
+    // Targeted advice for mistakes students make often
+    fn hint(&self, found: &Token) -> Option<String> {
+        let expects = |symbol: Token| self.expected.contains(&symbol);
+        let starts_statement = matches!(found, Token::LET | Token::RETURN | Token::PRINT | Token::PRINTLN
+            | Token::IF | Token::WHILE | Token::BREAK | Token::CONTINUE | Token::BRACKET_R);
+        let previous = self.source.prev_span();
+        if expects(Token::SEMICOLON) && (starts_statement || self.source.curr_span().line > previous.line) {
+            Some(format!("missing ';' after the statement ending at {previous}?"))
+        } else if (expects(Token::BRACKET_L) && *found == Token::BRACE_L)
+            || (expects(Token::BRACKET_R) && *found == Token::BRACE_R) {
+            Some("blocks are enclosed in '[' and ']', not '{' and '}'".to_string())
+        } else if expects(Token::EQ) && *found == Token::ASSIGN {
+            Some("use '==' to compare values, '=' assigns them".to_string())
+        } else if expects(Token::TYPE_INT32) {
+            Some("the available types are int32, flt32, char, string, bool and struct names".to_string())
+        } else {
+            None
+        }
     }
-    fn indent_decrement(&mut self) {
-        self.indent -= INDENT;
+
+    // Panic-mode recovery after a failed statement: skip tokens up to and
+    // including the next ';', or up to the ']' closing the enclosing block,
+    // treating a bracketed group as a single unit.
+    fn synchronize(&mut self) {
+        let mut depth = 0;
+        loop {
+            match self.curr() {
+                Token::EOI | Token::FUNC | Token::CONST | Token::STRUCT => return,
+                Token::SEMICOLON if depth == 0 => {
+                    self.advance();
+                    return;
+                }
+                Token::BRACKET_L => depth += 1,
+                Token::BRACKET_R => {
+                    if depth == 0 {
+                        return;
+                    }
+                    depth -= 1;
+                    if depth == 0 {
+                        self.advance();
+                        return;
+                    }
+                }
+                _ => {}
+            }
+            self.advance();
+        }
+    }
+}
+
+
+impl<S: TokenSource> DescentParser<S> { // utility functions for the trace
+
+    fn notify(&mut self, event: ParseEvent) {
+        if let Some(observer) = self.observer.as_mut() {
+            observer.on_event(&event);
+        }
     }
 
+    fn enter_rule(&mut self, rule: &'static str) {
+        self.rules.push((rule, self.source.curr_span()));
+        self.notify(ParseEvent::EnterRule(rule));
+    }
+
+    // returns the span of the tokens the rule consumed
+    fn exit_rule(&mut self) -> Span {
+        let (rule, start) = self.rules.pop().unwrap();
+        self.notify(ParseEvent::ExitRule(rule));
+        start.to(self.source.prev_span())
+    }
+
+    // leave every rule abandoned by a syntax error, back to the recovery point
+    fn unwind(&mut self, depth: usize) {
+        while self.rules.len() > depth {
+            let (rule, _) = self.rules.pop().unwrap();
+            self.notify(ParseEvent::Backtrack(rule));
+        }
+    }
+
+    // `event` is `ParseEvent::Consumed` or `ParseEvent::Accepted`
+    fn consume(&mut self, event: fn(Token, Span) -> ParseEvent) {
+        if self.observer.is_some() {
+            let event = event(self.curr(), self.source.curr_span());
+            self.notify(event);
+        }
+        self.advance();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token_source::ScriptedSource;
+
+    #[test]
+    fn a_lexer_collects_its_tokens_for_the_parser() {
+        let mut parser = DescentParser::new(Lexer::new("let x = 1; let y = 2;".to_string()));
+        let program = parser.analyze();
+        assert!(parser.errors().is_empty());
+        assert_eq!(program.children().len(), 2);
+
+        // collected beforehand, the parser still starts at the first token
+        let mut lexer = Lexer::new("let x = 1; let y = 2;".to_string());
+        lexer.collect_tokens();
+        let mut parser = DescentParser::new(lexer);
+        assert_eq!(parser.analyze().to_sexpr(), program.to_sexpr());
+    }
+
+    #[test]
+    fn scripted_tokens_without_source() {
+        // let x = 1 + ; let y = 2;
+        let tokens = vec![
+            Token::LET, Token::ID("x".to_string()), Token::ASSIGN, Token::LIT_INT32(1), Token::ADD, Token::SEMICOLON,
+            Token::LET, Token::ID("y".to_string()), Token::ASSIGN, Token::LIT_INT32(2), Token::SEMICOLON,
+        ];
+        let mut parser = DescentParser::new(ScriptedSource::new(tokens));
+        let program = parser.analyze();
+        assert_eq!(parser.errors().len(), 1);
+        assert_eq!(parser.errors()[0].span.column, 6);  // the ';' after '+'
+        let kinds: Vec<NodeType> = program.children().iter().map(|child| child.node_type()).collect();
+        assert_eq!(kinds, [NodeType::Error, NodeType::VariableDeclaration]);
+    }
+
+    // the syntax errors as line:column: message, and the program
+    fn parse_errors(source: &str) -> (Vec<String>, ParseTree) {
+        let mut parser = DescentParser::new(Lexer::new(source.to_string()));
+        let program = parser.analyze();
+        (parser.errors().iter().map(ParseError::to_string).collect(), program)
+    }
+
+    fn kinds(tree: &ParseTree) -> Vec<NodeType> {
+        tree.children().iter().map(|child| child.node_type()).collect()
+    }
+
+    #[test]
+    fn an_error_at_the_end_of_the_input_is_reported_once() {
+        let (errors, _) = parse_errors("func main() [ let x = ");
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        assert!(errors[0].starts_with("1:23: Expected one of '-', '!'"), "{errors:?}");
+        assert!(errors[0].ends_with("'{' or '(', but got end of input"), "{errors:?}");
+    }
+
+    #[test]
+    fn recovery_reports_every_broken_statement() {
+        let (errors, program) = parse_errors("func main() [ let x = 1 +; y = ; print 1;\n  while x [ break ]\n]\nfunc g() -> int32 [ return ]\nlet z = 3;\n");
+        let places: Vec<&str> = errors.iter().map(|error| error.split(' ').next().unwrap()).collect();
+        assert_eq!(places, ["1:26:", "1:32:", "2:19:", "4:28:"]);
+        assert!(errors[2].ends_with("Expected ';', but got ']'\n  hint: missing ';' after the statement ending at 2:13?"), "{errors:?}");
+        // the statements and definitions around the errors are kept
+        assert_eq!(kinds(&program), [NodeType::FunctionDefinition, NodeType::FunctionDefinition, NodeType::VariableDeclaration]);
+        let body = &program.children()[0].children()[1];
+        assert_eq!(kinds(body), [NodeType::Error, NodeType::Error, NodeType::PrintStatement, NodeType::WhileStatement]);
+    }
+
+    #[test]
+    fn recovery_at_the_top_level_stops_at_globals() {
+        let (errors, program) = parse_errors("x; let y = 2;\nlet z = y;\nfunc main() [ let a = 1; ]");
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        assert_eq!(kinds(&program), [NodeType::Error, NodeType::VariableDeclaration, NodeType::VariableDeclaration, NodeType::FunctionDefinition]);
+        // but not at the locals of a broken function
+        let (errors, program) = parse_errors("func f( [ let a = 1; [ let b = 2; ] ]\nlet g = 2;");
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        assert_eq!(kinds(&program), [NodeType::Error, NodeType::VariableDeclaration]);
+    }
+
+    fn fails_with(source: &str, message: &str) -> bool {
+        match crate::parse(source) {
+            Ok(_) => false,
+            Err(diagnostics) => diagnostics.iter().any(|diagnostic| diagnostic.message.starts_with(message)),
+        }
+    }
+
+    #[test]
+    fn long_operator_chains_are_too_long() {
+        let too_long = |source: &str| fails_with(source, "expression too long");
+        let sum = vec!["1"; 10_000].join(" + ");
+        assert!(too_long(&format!("let x = {sum};")));
+        assert!(too_long(&format!("let x = {}1;", "-".repeat(50_000))));
+        assert!(too_long(&format!("let x = a{};", "{0}".repeat(20_000))));
+        assert!(too_long(&format!("let x = p{};", ".x".repeat(20_000))));
+        // parentheses do not reset the count
+        let inner = vec!["1"; 450].join(" + ");
+        assert!(too_long(&format!("let x = {}{inner}{};", "(1 + ".repeat(100), ")".repeat(100))));
+    }
+
+    #[test]
+    fn deep_nesting_is_nested_too_deeply() {
+        assert!(fails_with(&format!("let x : int32{};", "{1}".repeat(100_000)), "nested too deeply"));
+        // a lower limit, the recursion up to the default one needs more than the stack of a test
+        let too_deep = |source: String| {
+            let mut parser = DescentParser::new(Lexer::new(source));
+            parser.set_max_depth(64);
+            parser.analyze();
+            parser.errors().iter().any(|error| error.message == "nested too deeply, the parser gives up at a depth of 64")
+        };
+        assert!(too_deep(format!("func main() [ {} ]", "[ ".repeat(40))));
+        assert!(too_deep(format!("let x = {}1{};", "(".repeat(40), ")".repeat(40))));
+        assert!(too_deep(format!("let x = f({}1{});", "g(".repeat(40), ")".repeat(40))));
+        assert!(!too_deep(format!("func main() [ {}{} ]", "[ ".repeat(20), " ]".repeat(20))));
+    }
+
+    #[test]
+    fn chains_within_the_depth_are_checked_and_run() {
+        let sum = vec!["1"; 400].join(" + ");
+        let program = crate::parse(&format!("func main() -> int32 [ println {sum}; return 0; ]")).unwrap();
+        assert!(crate::check(&program).is_empty());
+        let mut out = vec![];
+        crate::run(&program, &mut out).unwrap();
+        assert_eq!(out, b"400\n");
+    }
+
+    fn sexpr(source: &str) -> String {
+        crate::parse(source).unwrap().to_sexpr()
+    }
+
+    #[test]
+    fn while_loops_with_break_and_continue() {
+        assert_eq!(sexpr("func main() [ while i < 3 [ if i == 1 then [ continue; ] [ break; ] ] ]"),
+                   "(program (func main () (block (while (< i 3) (block (if (== i 1) (block (continue))) (block (break)))))))");
+        // only inside the body of a loop, not after it or in a function it calls
+        let (errors, _) = parse_errors("func main() [ break; while 1 < 2 [ ] continue; ]\nfunc f() [ while 1 < 2 [ g(); ] break; ]");
+        assert_eq!(errors, [
+            "1:15: 'break' is only allowed inside a while loop!",
+            "1:38: 'continue' is only allowed inside a while loop!",
+            "2:33: 'break' is only allowed inside a while loop!",
+        ]);
+        let (errors, _) = parse_errors("func main() [ while x [ break x; ] ]");
+        assert_eq!(errors, ["1:31: Expected ';', but got identifier 'x'"]);
+    }
+
+    #[test]
+    fn print_takes_a_list_of_expressions() {
+        assert_eq!(sexpr("func main() [ print \"x=\", x, \" y=\", f(y) * 2; println; println -x; ]"),
+                   "(program (func main () (block (print \"x=\" x \" y=\" (* (call f y) 2)) (println) (println (- x)))))");
+        // `print` needs something to print, and the values are separated by commas
+        let (errors, _) = parse_errors("func main() [ print; ]");
+        assert_eq!(errors, ["1:20: Expected one of '-', '!', integer literal, float literal, char literal, string literal, identifier, '{' or '(', but got ';'"]);
+        let (errors, _) = parse_errors("func main() [ print \"a\" x; ]");
+        assert_eq!(errors, ["1:25: Expected one of '+', '-', '*', '/', '==', '!=', '<', '>', '>=', '<=', ',' or ';', but got identifier 'x'"]);
+        let (errors, _) = parse_errors("func main() [ println 1,; ]");
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        assert!(errors[0].starts_with("1:25: Expected one of '-'"), "{errors:?}");
+    }
+
+    #[test]
+    fn statements_come_in_any_order() {
+        assert_eq!(sexpr("func f(x : int32) -> int32 [ print x; x = 1; [ ] return x; [ let y = x; [ println y; ] ] x = 2; return 3; ]"),
+                   "(program (func f ((x int32)) int32 (block (print x) (= x 1) (block) (return x) \
+                    (block (let y _ x) (block (println y))) (= x 2) (return 3))))");
+        // a return in the middle ends the function
+        let program = crate::parse("func main() [ print 1; [ println 2; return 0; ] println 3; ]").unwrap();
+        assert!(crate::check(&program).is_empty());
+        let mut out = vec![];
+        crate::run(&program, &mut out).unwrap();
+        assert_eq!(out, b"12\n");
+    }
+
+    #[test]
+    fn calls_take_expressions_and_nest() {
+        assert_eq!(sexpr("func main() [ log(x); let y = 1 + f(a + 1, g(b), \"s\") * h(); ]"),
+                   "(program (func main () (block (call log x) (let y _ (+ 1 (* (call f (+ a 1) (call g b) \"s\") (call h)))))))");
+        let (errors, _) = parse_errors("func main() [ f(let); ]");
+        assert!(errors[0].starts_with("1:17: Expected one of ')', '-', '!'"), "{errors:?}");
+        let (errors, _) = parse_errors("func main() [ f(1,); ]");
+        assert!(errors[0].starts_with("1:19: Expected one of '-', '!'"), "{errors:?}");
+    }
+
+    #[test]
+    fn let_with_and_without_type_and_value() {
+        assert_eq!(sexpr("func main() [ let a : int32 = b + 1; let c = 3; let d : flt32; ]"),
+                   "(program (func main () (block (let a int32 (+ b 1)) (let c _ 3) (let d flt32))))");
+        let (errors, _) = parse_errors("func main() [ let e; ]");
+        assert_eq!(errors, ["1:20: Expected one of ':' or '=', but got ';'"]);
+        let (errors, _) = parse_errors("func main() [ let f = ; ]");
+        assert!(errors[0].starts_with("1:23: Expected one of '-', '!'"), "{errors:?}");
+        let (errors, _) = parse_errors("func main() [ let g : = 1; ]");
+        assert_eq!(errors, ["1:23: Expected one of 'int32', 'flt32', 'char', 'string', 'bool' or identifier, but got '='\n  \
+                             hint: the available types are int32, flt32, char, string, bool and struct names"]);
+    }
+
+    #[test]
+    fn errors_list_every_expected_token_with_a_hint() {
+        let error = |source: &str| parse_errors(source).0.remove(0);
+        assert_eq!(error("func main() [ 5; ]"),
+                   "1:15: Expected one of ']', '[', 'let', identifier, 'return', 'print', 'println', 'if', 'while', 'break' or 'continue', \
+                    but got integer literal 5");
+        assert_eq!(error("func main() [ let a = 1 let b = 2; ]"),
+                   "1:25: Expected one of '+', '-', '*', '/', '==', '!=', '<', '>', '>=', '<=' or ';', but got 'let'\n  \
+                    hint: missing ';' after the statement ending at 1:23?");
+        assert_eq!(error("func main() [ x = 1\n]"),
+                   "2:1: Expected one of '+', '-', '*', '/', '==', '!=', '<', '>', '>=', '<=' or ';', but got ']'\n  \
+                    hint: missing ';' after the statement ending at 1:19?");
+        assert_eq!(error("func main() { }"),
+                   "1:13: Expected one of '->' or '[', but got '{'\n  hint: blocks are enclosed in '[' and ']', not '{' and '}'");
+        assert!(error("func main() [ if a = 1 then [ ] ]").ends_with("but got '='\n  hint: use '==' to compare values, '=' assigns them"));
+        assert_eq!(error("func main() [ ] ]"), "1:17: Expected one of end of input, 'func', 'let', 'const' or 'struct', but got ']'");
+    }
+
+    #[test]
+    fn spans_cover_the_source_of_each_node() {
+        let source = "func f(x : int32) -> int32 [\n    while x < 3 [ x = g(x, 1) + 2 * x; ]\n    return -x;\n]";
+        let program = crate::parse(source).unwrap();
+        let text = |tree: &ParseTree| &source[tree.span().start..tree.span().end];
+        let func = &program.children()[0];
+        assert_eq!(text(func), source);
+        assert_eq!(text(&func.children()[0]), "(x : int32)");
+        let body = &func.children()[2];
+        let [while_loop, ret] = body.children() else { panic!() };
+        assert_eq!(text(while_loop), "while x < 3 [ x = g(x, 1) + 2 * x; ]");
+        let assignment = &while_loop.children()[1].children()[0];
+        assert_eq!(text(assignment), "x = g(x, 1) + 2 * x;");
+        let sum = &assignment.children()[0];
+        assert_eq!([text(sum), text(&sum.children()[0]), text(&sum.children()[1])], ["g(x, 1) + 2 * x", "g(x, 1)", "2 * x"]);
+        assert_eq!(text(&ret.children()[0]), "-x");
+        assert_eq!((ret.span().line, ret.span().column), (3, 5));
+
+        // the diagnostics of the checker point at the node at fault
+        let source = "func main() [ let a = b + 1; f(a); ]";
+        let diagnostics = crate::check(&crate::parse(source).unwrap());
+        let places: Vec<&str> = diagnostics.iter().map(|diagnostic| {
+            let span = diagnostic.span.unwrap();
+            &source[span.start..span.end]
+        }).collect();
+        assert_eq!(places, ["b", "f(a);"]);  // a call statement, like an assignment, ends at its ';'
+    }
 }
@@ -0,0 +1,293 @@
+// Patterns over the parse tree, in the spirit of tree-sitter queries:
+//
+//   (WhileStatement !... (AssignmentStatement)) @loop
+//       every while loop without an assignment anywhere in its condition or body
+//   (PrintStatement (Literal :string) @first)
+//       every print statement whose first value is a string literal
+//   (FunctionCall "add" _ (Literal "0"))
+//       calls of add with 0 as the second argument
+//
+// <pattern> ::= ( _ | ( <kind> { <filter> } ) ) [ @<name> ]
+//   <kind>  a `NodeType` name like FunctionCall, or _ for any node
+// <filter> ::= "text"       the node's token text: a name, a literal's value or
+//                           an operator or keyword like "+" or "println"
+//            | :<type>      a literal of that type, or a Type node naming it
+//                           (int32, flt32, char, string or bool)
+//            | <pattern>    the next child matches, the first child pattern
+//                           is tried on the first child, and so on
+//            | ... <pattern> some node below this one matches
+//            | ! <filter>   the filter does not hold, for all but child patterns
+//
+// Every node of the tree is tried in preorder, a match reports the node and
+// the nodes captured with @<name>, outer captures first.
+
+use crate::parse_tree::{NodeType, ParseTree};
+use crate::parser_descent::ParseError;
+use crate::sexpr::{self, Sexpr};
+use crate::token::Token;
+
+pub struct Query {
+    pattern: Pattern,
+}
+
+struct Pattern {
+    kind: Option<NodeType>,  // None for _
+    filters: Vec<Filter>,
+    capture: Option<String>,
+}
+
+enum Filter {
+    Text(String),
+    Type(Token),  // TYPE_INT32, ...
+    Child(Pattern),
+    Descendant(Pattern),
+    Not(Box<Filter>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Match<'t> {
+    pub node: &'t ParseTree,
+    pub captures: Vec<(String, &'t ParseTree)>,
+}
+
+
+impl Query {
+    // Compile a pattern, errors point into `text`
+    pub fn new(text: &str) -> Result<Query, ParseError> {
+        let items = sexpr::read_all(text)?;
+        if items.is_empty() {
+            return Err(ParseError { message: "the query is empty".to_string(), span: Default::default(), hint: None });
+        }
+        let mut next = 0;
+        let pattern = read_pattern(&items, &mut next)?;
+        if let Some(extra) = items.get(next) {
+            return Err(error(extra, "expected the end of the query after the pattern"));
+        }
+        Ok(Query { pattern })
+    }
+
+    // every node matching the pattern, in preorder
+    pub fn matches<'t>(&self, tree: &'t ParseTree) -> Vec<Match<'t>> {
+        let mut found = vec![];
+        let mut pending = vec![tree];
+        while let Some(node) = pending.pop() {
+            let mut captures = vec![];
+            if matches(&self.pattern, node, &mut captures) {
+                found.push(Match { node, captures });
+            }
+            pending.extend(node.children().iter().rev().map(|child| child.as_ref()));
+        }
+        found
+    }
+}
+
+
+// Matching
+
+fn matches<'t>(pattern: &Pattern, tree: &'t ParseTree, captures: &mut Vec<(String, &'t ParseTree)>) -> bool {
+    let start = captures.len();
+    if pattern.kind.is_some_and(|kind| kind != tree.node_type()) {
+        return false;
+    }
+    let mut next_child = 0;
+    for filter in &pattern.filters {
+        if !holds(filter, tree, &mut next_child, captures) {
+            captures.truncate(start);
+            return false;
+        }
+    }
+    if let Some(name) = &pattern.capture {
+        captures.insert(start, (name.clone(), tree));
+    }
+    true
+}
+
+fn holds<'t>(filter: &Filter, tree: &'t ParseTree, next_child: &mut usize, captures: &mut Vec<(String, &'t ParseTree)>) -> bool {
+    match filter {
+        Filter::Text(text) => tree.token().text() == *text,
+        Filter::Type(type_token) => match tree.node_type() {
+            NodeType::Literal => literal_type(tree.token()).as_ref() == Some(type_token),
+            NodeType::Type => tree.token() == type_token,
+            _ => false,
+        },
+        Filter::Child(pattern) => {
+            let Some(child) = tree.children().get(*next_child) else {
+                return false;
+            };
+            *next_child += 1;
+            matches(pattern, child, captures)
+        }
+        Filter::Descendant(pattern) => {
+            let mut pending: Vec<&ParseTree> = tree.children().iter().rev().map(|child| child.as_ref()).collect();
+            while let Some(node) = pending.pop() {
+                if matches(pattern, node, captures) {
+                    return true;
+                }
+                pending.extend(node.children().iter().rev().map(|child| child.as_ref()));
+            }
+            false
+        }
+        Filter::Not(filter) => {
+            let mut ignored = vec![];
+            !holds(filter, tree, &mut next_child.clone(), &mut ignored)
+        }
+    }
+}
+
+fn literal_type(token: &Token) -> Option<Token> {
+    match token {
+        Token::LIT_INT32(_) => Some(Token::TYPE_INT32),
+        Token::LIT_FLT32(_) => Some(Token::TYPE_FLT32),
+        Token::LIT_CHAR(_) => Some(Token::TYPE_CHAR),
+        Token::LIT_STRING(_) => Some(Token::TYPE_STRING),
+        _ => None,
+    }
+}
+
+
+// Reading patterns
+
+fn error(sexpr: &Sexpr, message: &str) -> ParseError {
+    ParseError { message: message.to_string(), span: sexpr.span(), hint: None }
+}
+
+// the pattern at `items[*next]` and its capture, if any
+fn read_pattern(items: &[Sexpr], next: &mut usize) -> Result<Pattern, ParseError> {
+    let Some(item) = items.get(*next) else {
+        let span = items.last().map(Sexpr::span).unwrap_or_default();
+        return Err(ParseError { message: "expected a pattern at the end".to_string(), span, hint: None });
+    };
+    *next += 1;
+    let mut pattern = match item {
+        Sexpr::Atom(atom, _) if atom == "_" => Pattern { kind: None, filters: vec![], capture: None },
+        Sexpr::List(list, _) => {
+            let kind = match list.first() {
+                Some(Sexpr::Atom(atom, _)) if atom == "_" => None,
+                Some(head @ Sexpr::Atom(atom, _)) => {
+                    let kind = NodeType::ALL.into_iter().find(|kind| format!("{kind:?}") == *atom);
+                    Some(kind.ok_or_else(|| error(head, &format!("unknown node type '{atom}'")))?)
+                }
+                _ => return Err(error(item, "a pattern starts with a node type or _")),
+            };
+            let mut filters = vec![];
+            let mut at = 1;
+            while at < list.len() {
+                filters.push(read_filter(list, &mut at)?);
+            }
+            Pattern { kind, filters, capture: None }
+        }
+        _ => return Err(error(item, "expected a pattern, _ or (NodeType ...)")),
+    };
+    if let Some(Sexpr::Atom(atom, _)) = items.get(*next) {
+        if let Some(name) = atom.strip_prefix('@') {
+            if name.is_empty() {
+                return Err(error(&items[*next], "a capture needs a name, e.g. @loop"));
+            }
+            pattern.capture = Some(name.to_string());
+            *next += 1;
+        }
+    }
+    Ok(pattern)
+}
+
+fn read_filter(items: &[Sexpr], next: &mut usize) -> Result<Filter, ParseError> {
+    let item = &items[*next];
+    let filter = match item {
+        Sexpr::Str(text, _) => Filter::Text(text.clone()),
+        Sexpr::Char(c, _) => Filter::Text(c.to_string()),
+        Sexpr::List(..) => return Ok(Filter::Child(read_pattern(items, next)?)),
+        Sexpr::Atom(atom, _) => match atom.as_str() {
+            "_" => return Ok(Filter::Child(read_pattern(items, next)?)),
+            "..." | "!..." => {
+                *next += 1;
+                let descendant = Filter::Descendant(read_pattern(items, next)?);
+                return Ok(if atom == "..." { descendant } else { Filter::Not(Box::new(descendant)) });
+            }
+            "!" => {
+                *next += 1;
+                if *next == items.len() {
+                    return Err(error(item, "'!' needs a filter after it"));
+                }
+                let filter = read_filter(items, next)?;
+                if let Filter::Child(_) = filter {
+                    return Err(error(item, "'!' applies to text, type and '...' filters, not to a child pattern"));
+                }
+                return Ok(Filter::Not(Box::new(filter)));
+            }
+            _ if atom.starts_with(':') => {
+                let type_token = Token::TYPES.into_iter().find(|token| format!(":{}", token.text()) == *atom);
+                Filter::Type(type_token.ok_or_else(|| error(item, &format!("unknown type '{}'", &atom[1..])))?)
+            }
+            _ if atom.starts_with('@') => return Err(error(item, "a capture goes right after the pattern it names")),
+            _ => return Err(error(item, &format!("unknown filter '{atom}', a text filter is written in quotes"))),
+        },
+    };
+    *next += 1;
+    Ok(filter)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM: &str = "\
+func add(x : int32, y : int32) -> int32 [ return x + y; ]
+func main() [
+    let i = 0;
+    while i < 3 [ print \"i=\", i; i = i + 1; ]
+    while i > 0 [ println add(i, 0); ]
+    print add(1, 0), \"done\";
+]";
+
+    // each match as its node type and first line, then the captures by name
+    fn find(pattern: &str) -> Vec<String> {
+        let program = crate::parse(PROGRAM).unwrap();
+        let query = Query::new(pattern).unwrap();
+        query.matches(&program).iter().map(|found| {
+            let mut text = format!("{:?}@{}", found.node.node_type(), found.node.span().line);
+            for (name, node) in &found.captures {
+                text += &format!(" {name}={:?}", node.node_type());
+            }
+            text
+        }).collect()
+    }
+
+    fn compile_error(pattern: &str) -> String {
+        Query::new(pattern).err().unwrap().message
+    }
+
+    #[test]
+    fn header_examples() {
+        assert_eq!(find("(WhileStatement !... (AssignmentStatement)) @loop"), ["WhileStatement@5 loop=WhileStatement"]);
+        assert_eq!(find("(PrintStatement (Literal :string) @first)"), ["PrintStatement@4 first=Literal"]);
+        assert_eq!(find("(FunctionCall \"add\" _ (Literal \"0\"))"), ["FunctionCall@5", "FunctionCall@6"]);
+    }
+
+    #[test]
+    fn captures_outer_first() {
+        assert_eq!(find("(BinaryExpression \"+\" (Identifier) @left (Identifier \"y\") @right) @sum"),
+                   ["BinaryExpression@1 sum=BinaryExpression left=Identifier right=Identifier"]);
+        // a failed alternative leaves no captures behind
+        assert_eq!(find("(PrintStatement (Literal) @first (Identifier) @second)"),
+                   ["PrintStatement@4 first=Literal second=Identifier"]);
+    }
+
+    #[test]
+    fn negation() {
+        assert_eq!(find("(PrintStatement ! \"println\" ... (FunctionCall))"), ["PrintStatement@6"]);
+        assert_eq!(find("(FunctionDefinition !... (WhileStatement))"), ["FunctionDefinition@1"]);
+        assert_eq!(find("(Literal ! :int32)"), ["Literal@4", "Literal@6"]);
+    }
+
+    #[test]
+    fn compile_errors() {
+        assert_eq!(compile_error("(Loop)"), "unknown node type 'Loop'");
+        assert_eq!(compile_error("(WhileStatement) @"), "a capture needs a name, e.g. @loop");
+        assert_eq!(compile_error("(WhileStatement ! (Block))"), "'!' applies to text, type and '...' filters, not to a child pattern");
+        assert_eq!(compile_error("(Literal :int64)"), "unknown type 'int64'");
+        assert_eq!(compile_error(""), "the query is empty");
+        assert_eq!(compile_error("(Literal) (Literal)"), "expected the end of the query after the pattern");
+        assert_eq!(Query::new("(Loop)").err().unwrap().span.column, 2);
+    }
+}
@@ -0,0 +1,645 @@
+// Compact canonical S-expression form of the parse tree, e.g.
+//
+//   (program (func add ((x int32)) int32 (block (let value int32 35) (return (+ x value)))))
+//
+// for golden tests: `ParseTree::from_sexpr` reads the same form back, so an
+// expected tree can be written by hand and compared with `to_sexpr`.
+//
+//   (program ITEM...)                 (struct NAME (FIELD TYPE)...)
+//   (func NAME ((PARAM TYPE)...) [TYPE] BLOCK)
+//   (block STATEMENT...)              (let NAME TYPE|_ [VALUE])  (const NAME TYPE VALUE)
+//   (= NAME (index E)|(member F)... VALUE)
+//   (if COND BLOCK [BLOCK|IF])        (while COND BLOCK)         (return E)
+//   (print E...)  (println E...)      (break)  (continue)        (error)
+//   (call NAME ARG...)  (+ L R) ...   (- E)  (! E)  (array E...)
+//   (index ARRAY I)  (member STRUCT F)
+//   types: int32 ... bool, struct names, (array TYPE SIZE)
+//   atoms: identifiers, 35, 1.5, 'c', "string"
+//
+// Spans are not part of the form, trees read back have default spans.
+// An error node keeps only its node type and reads back with the EOI token.
+
+use crate::parse_tree::{NodeType, ParseTree};
+use crate::parser_descent::ParseError;
+use crate::token::{Span, Token};
+
+const BINARY_OPERATORS: [Token; 10] = [Token::ADD, Token::SUB, Token::MUL, Token::DIV,
+    Token::EQ, Token::NEQ, Token::LT, Token::GT, Token::NLT, Token::NGT];
+
+
+impl ParseTree {
+    pub fn to_sexpr(&self) -> String {
+        let mut out = String::new();
+        write_tree(self, &mut out);
+        out
+    }
+
+    pub fn from_sexpr(text: &str) -> Result<ParseTree, ParseError> {
+        let mut reader = Reader { input: text.chars().collect(), pos: 0, line: 1, column: 1 };
+        let sexpr = reader.read()?;
+        reader.skip_space();
+        if reader.pos < reader.input.len() {
+            return Err(reader.error("expected the end of the input after the tree"));
+        }
+        read_program(&sexpr)
+    }
+}
+
+
+// Every S-expression in `text`, in order
+pub(crate) fn read_all(text: &str) -> Result<Vec<Sexpr>, ParseError> {
+    let mut reader = Reader { input: text.chars().collect(), pos: 0, line: 1, column: 1 };
+    let mut sexprs = vec![];
+    loop {
+        reader.skip_space();
+        if reader.pos == reader.input.len() {
+            return Ok(sexprs);
+        }
+        sexprs.push(reader.read()?);
+    }
+}
+
+
+fn write_tree(tree: &ParseTree, out: &mut String) {
+    let children = tree.children();
+    let head = match tree.node_type() {
+        NodeType::Program => "program".to_string(),
+        NodeType::FunctionDefinition => format!("func {}", tree.name()),
+        NodeType::StructDefinition => format!("struct {}", tree.name()),
+        NodeType::Field | NodeType::Parameter => tree.name(),
+        NodeType::ParameterList => String::new(),
+        NodeType::Type => {
+            *out += &match tree.token() {
+                Token::ID(name) => name.clone(),
+                token => token.text(),
+            };
+            return;
+        }
+        NodeType::ArrayType => {
+            *out += "(array ";
+            write_tree(&children[0], out);
+            *out += &format!(" {})", size_of(tree));
+            return;
+        }
+        NodeType::Block => "block".to_string(),
+        NodeType::VariableDeclaration => {
+            *out += &format!("(let {}", tree.name());
+            if !matches!(children[0].node_type(), NodeType::Type | NodeType::ArrayType) {
+                *out += " _";
+            }
+            for child in children {
+                *out += " ";
+                write_tree(child, out);
+            }
+            *out += ")";
+            return;
+        }
+        NodeType::ConstantDeclaration => format!("const {}", tree.name()),
+        NodeType::AssignmentStatement => format!("= {}", tree.name()),
+        NodeType::IfStatement => "if".to_string(),
+        NodeType::ReturnStatement => "return".to_string(),
+        NodeType::PrintStatement => tree.token().text(),
+        NodeType::WhileStatement => "while".to_string(),
+        NodeType::BreakStatement => "break".to_string(),
+        NodeType::ContinueStatement => "continue".to_string(),
+        NodeType::FunctionCall => format!("call {}", tree.name()),
+        NodeType::BinaryExpression | NodeType::UnaryExpression => tree.token().text(),
+        NodeType::Literal => {
+            *out += &match tree.token() {
+                Token::LIT_INT32(value) => value.to_string(),
+                Token::LIT_FLT32(value) => format!("{value:?}"),
+                Token::LIT_CHAR(value) => format!("{value:?}"),
+                Token::LIT_STRING(value) => format!("{value:?}"),
+                token => token.text(),
+            };
+            return;
+        }
+        NodeType::Identifier => {
+            *out += &tree.name();
+            return;
+        }
+        NodeType::ArrayLiteral => "array".to_string(),
+        NodeType::IndexExpression | NodeType::Index => "index".to_string(),
+        NodeType::MemberExpression => {
+            *out += "(member ";
+            write_tree(&children[0], out);
+            *out += &format!(" {})", tree.name());
+            return;
+        }
+        NodeType::Member => format!("member {}", tree.name()),
+        NodeType::Error => "error".to_string(),
+    };
+    *out += "(";
+    *out += &head;
+    for (i, child) in children.iter().enumerate() {
+        if i > 0 || !head.is_empty() {
+            *out += " ";
+        }
+        write_tree(child, out);
+    }
+    *out += ")";
+}
+
+fn size_of(array_type: &ParseTree) -> i32 {
+    match array_type.token() {
+        Token::LIT_INT32(size) => *size,
+        _ => 0,
+    }
+}
+
+
+// An S-expression before it is given a meaning as part of the tree, also
+// read by `query` for its patterns
+pub(crate) enum Sexpr {
+    Atom(String, Span),
+    Str(String, Span),
+    Char(char, Span),
+    List(Vec<Sexpr>, Span),
+}
+
+impl Sexpr {
+    pub(crate) fn span(&self) -> Span {
+        match self {
+            Sexpr::Atom(_, span) | Sexpr::Str(_, span) | Sexpr::Char(_, span) | Sexpr::List(_, span) => *span,
+        }
+    }
+
+    // the elements of a list starting with `head`, after the head
+    fn form(&self, head: &str) -> Option<&[Sexpr]> {
+        match self {
+            Sexpr::List(items, _) => match items.first() {
+                Some(Sexpr::Atom(atom, _)) if atom == head => Some(&items[1..]),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn head(&self) -> Option<&str> {
+        match self {
+            Sexpr::List(items, _) => match items.first() {
+                Some(Sexpr::Atom(atom, _)) => Some(atom),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+
+struct Reader {
+    input: Vec<char>,
+    pos: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Reader {
+
+    fn read(&mut self) -> Result<Sexpr, ParseError> {
+        self.skip_space();
+        let start = self.span();
+        match self.input.get(self.pos) {
+            None => Err(self.error("unexpected end of input")),
+            Some('(') => {
+                self.bump();
+                let mut items = vec![];
+                loop {
+                    self.skip_space();
+                    match self.input.get(self.pos) {
+                        Some(')') => break,
+                        None => return Err(self.error("missing ')'")),
+                        _ => items.push(self.read()?),
+                    }
+                }
+                self.bump();
+                Ok(Sexpr::List(items, start.to(self.span())))
+            }
+            Some(')') => Err(self.error("unexpected ')'")),
+            Some('"') => {
+                let text = self.read_quoted('"')?;
+                Ok(Sexpr::Str(text, start.to(self.span())))
+            }
+            Some('\'') => {
+                let text = self.read_quoted('\'')?;
+                let mut chars = text.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Ok(Sexpr::Char(c, start.to(self.span()))),
+                    _ => Err(ParseError { message: "a char literal holds exactly one character".to_string(), span: start, hint: None }),
+                }
+            }
+            Some(_) => {
+                let mut atom = String::new();
+                while let Some(&c) = self.input.get(self.pos) {
+                    if c.is_whitespace() || c == '(' || c == ')' {
+                        break;
+                    }
+                    atom.push(c);
+                    self.bump();
+                }
+                Ok(Sexpr::Atom(atom, start.to(self.span())))
+            }
+        }
+    }
+
+    // a string or char literal written like Rust's `{:?}`
+    fn read_quoted(&mut self, quote: char) -> Result<String, ParseError> {
+        self.bump();
+        let mut text = String::new();
+        loop {
+            let Some(&c) = self.input.get(self.pos) else {
+                return Err(self.error(&format!("missing closing {quote}")));
+            };
+            self.bump();
+            match c {
+                c if c == quote => return Ok(text),
+                '\\' => {
+                    let escaped = self.input.get(self.pos).copied();
+                    self.bump();
+                    text.push(match escaped {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('0') => '\0',
+                        Some('u') => self.read_unicode()?,
+                        Some(c @ ('\\' | '"' | '\'')) => c,
+                        _ => return Err(self.error("unknown escape sequence")),
+                    });
+                }
+                c => text.push(c),
+            }
+        }
+    }
+
+    // the rest of a \u{...} escape
+    fn read_unicode(&mut self) -> Result<char, ParseError> {
+        let mut digits = String::new();
+        if self.input.get(self.pos) == Some(&'{') {
+            self.bump();
+            while let Some(&c) = self.input.get(self.pos) {
+                self.bump();
+                if c == '}' {
+                    break;
+                }
+                digits.push(c);
+            }
+        }
+        u32::from_str_radix(&digits, 16).ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| self.error("invalid unicode escape"))
+    }
+
+    fn skip_space(&mut self) {
+        while self.input.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.bump();
+        }
+    }
+
+    fn bump(&mut self) {
+        if self.input.get(self.pos) == Some(&'\n') {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        self.pos += 1;
+    }
+
+    fn span(&self) -> Span {
+        Span { start: self.pos, end: self.pos, line: self.line, column: self.column }
+    }
+
+    fn error(&self, message: &str) -> ParseError {
+        ParseError { message: message.to_string(), span: self.span(), hint: None }
+    }
+}
+
+
+type ReadResult = Result<ParseTree, ParseError>;
+
+fn error(sexpr: &Sexpr, message: String) -> ParseError {
+    ParseError { message, span: sexpr.span(), hint: None }
+}
+
+fn read_program(sexpr: &Sexpr) -> ReadResult {
+    let items = sexpr.form("program").ok_or_else(|| error(sexpr, "expected (program ...)".to_string()))?;
+    let mut program = ParseTree::new(Token::EOI, NodeType::Program);
+    for item in items {
+        program.push(match item.head() {
+            Some("func") => read_func(item)?,
+            Some("struct") => read_struct(item)?,
+            Some("let" | "const" | "error") => read_statement(item)?,
+            _ => return Err(error(item, "expected a func, struct, let or const".to_string())),
+        });
+    }
+    Ok(program)
+}
+
+fn read_func(sexpr: &Sexpr) -> ReadResult {
+    let items = sexpr.form("func").unwrap();
+    let (name, rest) = split_name(sexpr, items)?;
+    let mut func = ParseTree::new(name, NodeType::FunctionDefinition);
+    let Some((Sexpr::List(parameters, _), rest)) = rest.split_first() else {
+        return Err(error(sexpr, "expected the parameter list after the function name".to_string()));
+    };
+    let mut list = ParseTree::new(Token::PARENS_L, NodeType::ParameterList);
+    for parameter in parameters {
+        list.push(read_typed(parameter, NodeType::Parameter)?);
+    }
+    func.push(list);
+    match rest {
+        [body] => func.push(read_block(body)?),
+        [return_type, body] => {
+            func.push(read_type(return_type)?);
+            func.push(read_block(body)?);
+        }
+        _ => return Err(error(sexpr, "expected an optional return type and the body".to_string())),
+    }
+    Ok(func)
+}
+
+fn read_struct(sexpr: &Sexpr) -> ReadResult {
+    let items = sexpr.form("struct").unwrap();
+    let (name, fields) = split_name(sexpr, items)?;
+    let mut definition = ParseTree::new(name, NodeType::StructDefinition);
+    for field in fields {
+        definition.push(read_typed(field, NodeType::Field)?);
+    }
+    Ok(definition)
+}
+
+// (NAME TYPE) for parameters and fields
+fn read_typed(sexpr: &Sexpr, node_type: NodeType) -> ReadResult {
+    match sexpr {
+        Sexpr::List(items, _) if items.len() == 2 => {
+            let mut tree = ParseTree::new(read_name(&items[0])?, node_type);
+            tree.push(read_type(&items[1])?);
+            Ok(tree)
+        }
+        _ => Err(error(sexpr, "expected (NAME TYPE)".to_string())),
+    }
+}
+
+fn read_type(sexpr: &Sexpr) -> ReadResult {
+    if let Some(items) = sexpr.form("array") {
+        let [element, Sexpr::Atom(size, _)] = items else {
+            return Err(error(sexpr, "expected (array TYPE SIZE)".to_string()));
+        };
+        let size = size.parse::<i32>().map_err(|_| error(&items[1], format!("invalid array size '{size}'")))?;
+        let mut array = ParseTree::new(Token::LIT_INT32(size), NodeType::ArrayType);
+        array.push(read_type(element)?);
+        return Ok(array);
+    }
+    match sexpr {
+        Sexpr::Atom(atom, _) => {
+            let token = Token::TYPES.iter().find(|token| token.text() == *atom).cloned();
+            let token = match token {
+                Some(token) => token,
+                None => read_name(sexpr)?,
+            };
+            Ok(ParseTree::new(token, NodeType::Type))
+        }
+        _ => Err(error(sexpr, "expected a type".to_string())),
+    }
+}
+
+fn read_block(sexpr: &Sexpr) -> ReadResult {
+    let statements = sexpr.form("block").ok_or_else(|| error(sexpr, "expected (block ...)".to_string()))?;
+    let mut block = ParseTree::new(Token::BRACKET_L, NodeType::Block);
+    for statement in statements {
+        block.push(read_statement(statement)?);
+    }
+    Ok(block)
+}
+
+fn read_statement(sexpr: &Sexpr) -> ReadResult {
+    let Some(head) = sexpr.head() else {
+        return Err(error(sexpr, "expected a statement".to_string()));
+    };
+    let items = sexpr.form(head).unwrap();
+    let tree = match head {
+        "block" => read_block(sexpr)?,
+        "let" => {
+            let (name, rest) = split_name(sexpr, items)?;
+            let mut declaration = ParseTree::new(name, NodeType::VariableDeclaration);
+            match rest {
+                [declared] => declaration.push(read_type(declared)?),
+                [Sexpr::Atom(blank, _), value] if blank == "_" => declaration.push(read_expression(value)?),
+                [declared, value] => {
+                    declaration.push(read_type(declared)?);
+                    declaration.push(read_expression(value)?);
+                }
+                _ => return Err(error(sexpr, "expected (let NAME TYPE|_ [VALUE])".to_string())),
+            }
+            declaration
+        }
+        "const" => {
+            let (name, rest) = split_name(sexpr, items)?;
+            let [declared, value] = rest else {
+                return Err(error(sexpr, "expected (const NAME TYPE VALUE)".to_string()));
+            };
+            let mut declaration = ParseTree::new(name, NodeType::ConstantDeclaration);
+            declaration.push(read_type(declared)?);
+            declaration.push(read_expression(value)?);
+            declaration
+        }
+        "=" => {
+            let (name, rest) = split_name(sexpr, items)?;
+            let Some((value, selectors)) = rest.split_last() else {
+                return Err(error(sexpr, "expected (= NAME SELECTOR... VALUE)".to_string()));
+            };
+            let mut assignment = ParseTree::new(name, NodeType::AssignmentStatement);
+            for selector in selectors {
+                assignment.push(read_selector(selector)?);
+            }
+            assignment.push(read_expression(value)?);
+            assignment
+        }
+        "if" => {
+            let mut statement = ParseTree::new(Token::IF, NodeType::IfStatement);
+            match items {
+                [condition, then, rest @ ..] if rest.len() <= 1 => {
+                    statement.push(read_expression(condition)?);
+                    statement.push(read_block(then)?);
+                    if let Some(otherwise) = rest.first() {
+                        statement.push(match otherwise.head() {
+                            Some("if") => read_statement(otherwise)?,
+                            _ => read_block(otherwise)?,
+                        });
+                    }
+                }
+                _ => return Err(error(sexpr, "expected (if COND BLOCK [ELSE])".to_string())),
+            }
+            statement
+        }
+        "while" => {
+            let [condition, body] = items else {
+                return Err(error(sexpr, "expected (while COND BLOCK)".to_string()));
+            };
+            let mut statement = ParseTree::new(Token::WHILE, NodeType::WhileStatement);
+            statement.push(read_expression(condition)?);
+            statement.push(read_block(body)?);
+            statement
+        }
+        "return" => {
+            let [value] = items else {
+                return Err(error(sexpr, "expected (return VALUE)".to_string()));
+            };
+            let mut statement = ParseTree::new(Token::RETURN, NodeType::ReturnStatement);
+            statement.push(read_expression(value)?);
+            statement
+        }
+        "print" | "println" => {
+            let token = if head == "print" { Token::PRINT } else { Token::PRINTLN };
+            let mut statement = ParseTree::new(token, NodeType::PrintStatement);
+            for value in items {
+                statement.push(read_expression(value)?);
+            }
+            statement
+        }
+        "break" if items.is_empty() => ParseTree::new(Token::BREAK, NodeType::BreakStatement),
+        "continue" if items.is_empty() => ParseTree::new(Token::CONTINUE, NodeType::ContinueStatement),
+        "error" if items.is_empty() => ParseTree::new(Token::EOI, NodeType::Error),
+        "call" => read_expression(sexpr)?,
+        _ => return Err(error(sexpr, format!("unknown statement '{head}'"))),
+    };
+    Ok(tree)
+}
+
+// (index I) or (member F) on the left of an assignment
+fn read_selector(sexpr: &Sexpr) -> ReadResult {
+    if let Some([index]) = sexpr.form("index") {
+        let mut selector = ParseTree::new(Token::BRACE_L, NodeType::Index);
+        selector.push(read_expression(index)?);
+        return Ok(selector);
+    }
+    if let Some([field]) = sexpr.form("member") {
+        return Ok(ParseTree::new(read_name(field)?, NodeType::Member));
+    }
+    Err(error(sexpr, "expected (index I) or (member FIELD)".to_string()))
+}
+
+fn read_expression(sexpr: &Sexpr) -> ReadResult {
+    let tree = match sexpr {
+        Sexpr::Str(value, _) => ParseTree::new(Token::LIT_STRING(value.clone()), NodeType::Literal),
+        Sexpr::Char(value, _) => ParseTree::new(Token::LIT_CHAR(*value), NodeType::Literal),
+        Sexpr::Atom(atom, _) => {
+            if let Ok(value) = atom.parse::<i32>() {
+                ParseTree::new(Token::LIT_INT32(value), NodeType::Literal)
+            } else if atom.starts_with(|c: char| c.is_ascii_digit()) {
+                let value = atom.parse::<f32>().map_err(|_| error(sexpr, format!("invalid number '{atom}'")))?;
+                ParseTree::new(Token::LIT_FLT32(value), NodeType::Literal)
+            } else {
+                ParseTree::new(read_name(sexpr)?, NodeType::Identifier)
+            }
+        }
+        Sexpr::List(items, _) => {
+            let Some(head) = sexpr.head() else {
+                return Err(error(sexpr, "expected an expression".to_string()));
+            };
+            let operands = &items[1..];
+            let operator = BINARY_OPERATORS.iter().chain(&[Token::NOT]).find(|token| token.text() == head);
+            match (head, operands) {
+                ("call", _) => {
+                    let (name, arguments) = split_name(sexpr, operands)?;
+                    let mut call = ParseTree::new(name, NodeType::FunctionCall);
+                    for argument in arguments {
+                        call.push(read_expression(argument)?);
+                    }
+                    call
+                }
+                ("array", _) => {
+                    let mut array = ParseTree::new(Token::BRACE_L, NodeType::ArrayLiteral);
+                    for element in operands {
+                        array.push(read_expression(element)?);
+                    }
+                    array
+                }
+                ("index", [array, index]) => {
+                    let mut expression = ParseTree::new(Token::BRACE_L, NodeType::IndexExpression);
+                    expression.push(read_expression(array)?);
+                    expression.push(read_expression(index)?);
+                    expression
+                }
+                ("member", [value, field]) => {
+                    let mut expression = ParseTree::new(read_name(field)?, NodeType::MemberExpression);
+                    expression.push(read_expression(value)?);
+                    expression
+                }
+                ("-" | "!", [operand]) => {
+                    let mut expression = ParseTree::new(operator.unwrap().clone(), NodeType::UnaryExpression);
+                    expression.push(read_expression(operand)?);
+                    expression
+                }
+                (_, [left, right]) if operator.is_some() && head != "!" => {
+                    ParseTree::binary(operator.unwrap().clone(), read_expression(left)?, read_expression(right)?)
+                }
+                _ => return Err(error(sexpr, format!("unknown expression '{head}'"))),
+            }
+        }
+    };
+    Ok(tree)
+}
+
+fn read_name(sexpr: &Sexpr) -> Result<Token, ParseError> {
+    match sexpr {
+        Sexpr::Atom(atom, _) if atom.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && atom.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') => Ok(Token::ID(atom.clone())),
+        _ => Err(error(sexpr, "expected a name".to_string())),
+    }
+}
+
+// the name following the head of a form, and the items after it
+fn split_name<'s>(sexpr: &Sexpr, items: &'s [Sexpr]) -> Result<(Token, &'s [Sexpr]), ParseError> {
+    match items.split_first() {
+        Some((name, rest)) => Ok((read_name(name)?, rest)),
+        None => Err(error(sexpr, "expected a name".to_string())),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM: &str = "\
+struct Point [ x : flt32, y : flt32 ]
+const LIMIT : int32 = 3;
+let grid : int32{2}{3};
+func main() -> int32 [
+    let p = Point(1.5, -2.0);
+    let c : char = 'c';
+    let i = 0;
+    while i < LIMIT [
+        if i == 1 then [ i = i + 1; continue; ] else if !(i > 5) then [ grid{0}{i} = i; ] else [ break; ]
+        i = i + 1;
+    ]
+    p.x = p.y * 2.5;
+    println \"done \", grid{0}, \" \", Point(1.0, 2.0).y;
+    return 0;
+]";
+
+    #[test]
+    fn round_trip() {
+        for source in [include_str!("../samples/sum.txt"), PROGRAM] {
+            let sexpr = crate::parse(source).unwrap().to_sexpr();
+            assert_eq!(ParseTree::from_sexpr(&sexpr).unwrap().to_sexpr(), sexpr);
+        }
+    }
+
+    #[test]
+    fn expected_tree() {
+        let tree = crate::parse("func add(x : int32) -> int32 [ let value : int32 = 35; return x + value * 2; ]").unwrap();
+        let expected = "(program (func add ((x int32)) int32
+                           (block (let value int32 35)
+                                  (return (+ x (* value 2))))))";
+        assert_eq!(tree.to_sexpr(), ParseTree::from_sexpr(expected).unwrap().to_sexpr());
+        assert_eq!(tree.to_sexpr(), expected.split_whitespace().collect::<Vec<_>>().join(" "));
+    }
+
+    #[test]
+    fn errors_point_into_the_text() {
+        let error = ParseTree::from_sexpr("(program (func f () (block (retrun 1))))").unwrap_err();
+        assert_eq!((error.span.line, error.span.column), (1, 28));
+    }
+}
@@ -0,0 +1,217 @@
+use crate::parse_tree::{NodeType, ParseTree};
+use crate::token::{Span, Token};
+
+// Index of a node in a `SyntaxTree`. Ids are given out in preorder, so the
+// root is 0, a parent's id is smaller than its children's, and siblings are
+// numbered left to right. `index()` makes them usable for dense side tables:
+//
+//   let mut types = vec![None; tree.len()];
+//   types[id.index()] = Some(Type::Int32);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NodeId(u32);
+
+impl NodeId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Node {
+    pub token: Token,
+    pub node_type: NodeType,
+    pub span: Span,
+    pub parent: Option<NodeId>,  // None for the root
+    pub children: Vec<NodeId>,
+}
+
+
+// The parse tree flattened into one vector of nodes, with links in both
+// directions. Semantic passes can walk up from any node and keep their results
+// in a `HashMap<NodeId, _>` or a vector indexed by `NodeId::index()`; cloning
+// the tree is a single vector copy.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SyntaxTree {
+    nodes: Vec<Node>,
+}
+
+impl SyntaxTree {
+    pub fn new(tree: &ParseTree) -> SyntaxTree {
+        let mut nodes: Vec<Node> = vec![];
+        let mut pending = vec![(tree, None)];  // node and its parent
+        while let Some((tree, parent)) = pending.pop() {
+            let id = NodeId(nodes.len() as u32);
+            nodes.push(Node {
+                token: tree.token().clone(),
+                node_type: tree.node_type(),
+                span: tree.span(),
+                parent,
+                children: vec![],
+            });
+            if let Some(parent) = parent {
+                nodes[parent.index()].children.push(id);
+            }
+            for child in tree.children().iter().rev() {
+                pending.push((child, Some(id)));
+            }
+        }
+        SyntaxTree { nodes }
+    }
+
+    // Build the nested `ParseTree` back, with the same tokens and spans
+    pub fn to_parse_tree(&self) -> ParseTree {
+        // children have larger ids than their parents, so building from the
+        // last node backwards finds every child already built
+        let mut built: Vec<Option<ParseTree>> = vec![None; self.nodes.len()];
+        for (i, node) in self.nodes.iter().enumerate().rev() {
+            let mut tree = ParseTree::new(node.token.clone(), node.node_type).with_span(node.span);
+            for child in &node.children {
+                tree.push(built[child.index()].take().unwrap());
+            }
+            built[i] = Some(tree);
+        }
+        built[0].take().unwrap()
+    }
+
+    pub fn root(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    // the number of nodes, ids run from 0 to len() - 1
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.index()]
+    }
+
+    pub fn token(&self, id: NodeId) -> &Token {
+        &self.node(id).token
+    }
+
+    pub fn node_type(&self, id: NodeId) -> NodeType {
+        self.node(id).node_type
+    }
+
+    pub fn span(&self, id: NodeId) -> Span {
+        self.node(id).span
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id).parent
+    }
+
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        &self.node(id).children
+    }
+
+    // the identifier this node is named by, like `ParseTree::name`
+    pub fn name(&self, id: NodeId) -> String {
+        match self.token(id) {
+            Token::ID(name) => name.clone(),
+            token => format!("{token:?}"),
+        }
+    }
+
+    // every id, which is also the preorder of the tree
+    pub fn ids(&self) -> impl Iterator<Item = NodeId> {
+        (0..self.nodes.len() as u32).map(NodeId)
+    }
+
+    // the parent, its parent and so on up to the root
+    pub fn ancestors(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        std::iter::successors(self.parent(id), |&id| self.parent(id))
+    }
+
+    // `id` and everything below it in preorder
+    pub fn descendants(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        let mut pending = vec![id];
+        std::iter::from_fn(move || {
+            let id = pending.pop()?;
+            pending.extend(self.children(id).iter().rev());
+            Some(id)
+        })
+    }
+
+    // the nearest ancestor of the given type, e.g. the function of a return
+    pub fn enclosing(&self, id: NodeId, node_type: NodeType) -> Option<NodeId> {
+        self.ancestors(id).find(|&ancestor| self.node_type(ancestor) == node_type)
+    }
+
+    // the deepest node whose span contains the character `offset`
+    pub fn node_at(&self, offset: usize) -> Option<NodeId> {
+        let contains = |id: NodeId| {
+            let span = self.span(id);
+            span != Span::default() && span.start <= offset && offset < span.end
+        };
+        let mut found = None;
+        let mut id = self.root();
+        loop {
+            if contains(id) {
+                found = Some(id);
+            }
+            match self.children(id).iter().find(|&&child| contains(child)) {
+                Some(&child) => id = child,
+                None => return found,
+            }
+        }
+    }
+}
+
+impl From<&ParseTree> for SyntaxTree {
+    fn from(tree: &ParseTree) -> SyntaxTree {
+        SyntaxTree::new(tree)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM: &str = "\
+struct P [ x : int32 ]
+func main() [
+    let p = P(1);
+    while p.x < 3 [ p.x = p.x + 1; ]
+    println p.x;
+]";
+
+    // every node with its token, type, span and links
+    fn nodes(tree: &SyntaxTree) -> Vec<String> {
+        tree.ids().map(|id| format!("{:?}", tree.node(id))).collect()
+    }
+
+    #[test]
+    fn round_trip_through_the_parse_tree() {
+        let parsed = crate::parse(PROGRAM).unwrap();
+        let tree = SyntaxTree::new(&parsed);
+        let rebuilt = tree.to_parse_tree();
+        assert_eq!(rebuilt.to_sexpr(), parsed.to_sexpr());
+        assert_eq!(nodes(&SyntaxTree::from(&rebuilt)), nodes(&tree));
+    }
+
+    #[test]
+    fn ids_are_preorder_with_links_both_ways() {
+        let tree = SyntaxTree::new(&crate::parse(PROGRAM).unwrap());
+        assert_eq!(tree.ids().collect::<Vec<_>>(), tree.descendants(tree.root()).collect::<Vec<_>>());
+        for id in tree.ids().skip(1) {
+            let parent = tree.parent(id).unwrap();
+            assert!(parent < id && tree.children(parent).contains(&id));
+        }
+        // from the `3` in the loop condition up to its function
+        let three = tree.node_at(PROGRAM.find("3 [").unwrap()).unwrap();
+        assert_eq!(tree.node_type(three), NodeType::Literal);
+        let function = tree.enclosing(three, NodeType::FunctionDefinition).unwrap();
+        assert_eq!(tree.name(function), "main");
+        assert_eq!(tree.ancestors(three).last(), Some(tree.root()));
+    }
+}
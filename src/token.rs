@@ -1,7 +1,9 @@
+use std::fmt;
 use std::mem::discriminant;
 
 #[derive(Debug, Clone)]
-#[allow(non_camel_case_types, dead_code)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[allow(non_camel_case_types, dead_code, clippy::upper_case_acronyms)]
 pub enum Token {
     PARENS_L,
     PARENS_R,
@@ -35,6 +37,7 @@ pub enum Token {
     THEN,
     WHILE,
     PRINT,
+    PRINTLN,
     ID(String),
     TYPE_INT32,
     TYPE_FLT32,
@@ -47,6 +50,10 @@ pub enum Token {
     LIT_STRING(String),
     EOI,
     RETURN,
+    BREAK,
+    CONTINUE,
+    CONST,
+    STRUCT,
 }
 
 impl PartialEq for Token {
@@ -59,6 +66,9 @@ impl Eq for Token { }
 
 #[allow(dead_code)]
 impl Token {
+    // the built-in types, int32 ... bool
+    pub const TYPES: [Token; 5] = [Token::TYPE_INT32, Token::TYPE_FLT32, Token::TYPE_CHAR, Token::TYPE_STRING, Token::TYPE_BOOL];
+
     pub fn id() -> Token {
         Token::ID(String::new())
     }
@@ -74,4 +84,129 @@ impl Token {
     pub fn lit_string() -> Token {
         Token::LIT_STRING(String::new())
     }
-}
\ No newline at end of file
+
+    // Human readable name of the token kind, used in diagnostics
+    pub fn describe(&self) -> String {
+        let name = match self {
+            Token::PARENS_L => "'('",
+            Token::PARENS_R => "')'",
+            Token::BRACKET_L => "'['",
+            Token::BRACKET_R => "']'",
+            Token::BRACE_L => "'{'",
+            Token::BRACE_R => "'}'",
+            Token::POINT => "'.'",
+            Token::COMMA => "','",
+            Token::COLON => "':'",
+            Token::SEMICOLON => "';'",
+            Token::ARROW_R => "'->'",
+            Token::ADD => "'+'",
+            Token::SUB => "'-'",
+            Token::MUL => "'*'",
+            Token::DIV => "'/'",
+            Token::EQ => "'=='",
+            Token::LT => "'<'",
+            Token::GT => "'>'",
+            Token::NEQ => "'!='",
+            Token::NLT => "'>='",
+            Token::NGT => "'<='",
+            Token::NOT => "'!'",
+            Token::AND => "'&&'",
+            Token::OR => "'||'",
+            Token::ASSIGN => "'='",
+            Token::FUNC => "'func'",
+            Token::LET => "'let'",
+            Token::IF => "'if'",
+            Token::ELSE => "'else'",
+            Token::THEN => "'then'",
+            Token::WHILE => "'while'",
+            Token::PRINT => "'print'",
+            Token::PRINTLN => "'println'",
+            Token::ID(_) => "identifier",
+            Token::TYPE_INT32 => "'int32'",
+            Token::TYPE_FLT32 => "'flt32'",
+            Token::TYPE_CHAR => "'char'",
+            Token::TYPE_STRING => "'string'",
+            Token::TYPE_BOOL => "'bool'",
+            Token::LIT_INT32(_) => "integer literal",
+            Token::LIT_FLT32(_) => "float literal",
+            Token::LIT_CHAR(_) => "char literal",
+            Token::LIT_STRING(_) => "string literal",
+            Token::EOI => "end of input",
+            Token::RETURN => "'return'",
+            Token::BREAK => "'break'",
+            Token::CONTINUE => "'continue'",
+            Token::CONST => "'const'",
+            Token::STRUCT => "'struct'",
+        };
+        name.to_string()
+    }
+
+    // The token as written in the source: "+", "while", "int32", the name of
+    // an identifier or the value of a literal (without quotes)
+    pub fn text(&self) -> String {
+        match self {
+            Token::ID(name) => name.clone(),
+            Token::LIT_INT32(value) => value.to_string(),
+            Token::LIT_FLT32(value) => value.to_string(),
+            Token::LIT_CHAR(value) => value.to_string(),
+            Token::LIT_STRING(value) => value.clone(),
+            Token::EOI => String::new(),
+            token => token.describe().trim_matches('\'').to_string(),
+        }
+    }
+
+    // Like `describe`, but includes the value of identifiers and literals
+    pub fn describe_found(&self) -> String {
+        match self {
+            Token::ID(name) => format!("identifier '{name}'"),
+            Token::LIT_INT32(value) => format!("integer literal {value}"),
+            Token::LIT_FLT32(value) => format!("float literal {value}"),
+            Token::LIT_CHAR(value) => format!("char literal {value:?}"),
+            Token::LIT_STRING(value) => format!("string literal {value:?}"),
+            _ => self.describe(),
+        }
+    }
+}
+
+// Position of a token in the source, `start..end` are character offsets,
+// `line` and `column` are 1-based and refer to `start`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    // The span from the start of `self` to the end of `last`
+    pub fn to(self, last: Span) -> Span {
+        Span { end: last.end.max(self.start), ..self }
+    }
+
+    // The smallest span containing both, a default (empty) span counts as nothing
+    pub fn cover(self, other: Span) -> Span {
+        if self == Span::default() {
+            return other;
+        }
+        if other == Span::default() {
+            return self;
+        }
+        let first = if other.start < self.start { other } else { self };
+        Span { end: self.end.max(other.end), ..first }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
@@ -0,0 +1,134 @@
+use crate::parser_descent::ParseError;
+use crate::token::{Span, SpannedToken, Token};
+
+// Where `DescentParser` reads its tokens from. A source behaves as if the
+// token stream ends with EOI forever, no matter how far it is advanced.
+pub trait TokenSource {
+    // called by the parser before it reads the first token
+    fn begin(&mut self) {}
+
+    // the token at the current position
+    fn current(&self) -> Token;
+
+    // the token after the current one
+    fn peek_next(&self) -> Token;
+
+    // move on to the next token
+    fn bump(&mut self);
+
+    // the number of tokens consumed so far
+    fn position(&self) -> usize;
+
+    fn curr_span(&self) -> Span;
+
+    // the span of the last consumed token
+    fn prev_span(&self) -> Span;
+
+    // tokens that could not be read, reported along with the syntax errors
+    fn errors(&self) -> Vec<ParseError> {
+        vec![]
+    }
+}
+
+
+// Tokens collected beforehand, e.g. by `lex` or a preprocessing pass
+pub struct TokenStream {
+    tokens: Vec<SpannedToken>,
+    pos: usize,
+}
+
+impl TokenStream {
+    pub fn new(mut tokens: Vec<SpannedToken>) -> TokenStream {
+        if tokens.last().map(|last| &last.token) != Some(&Token::EOI) {
+            // end of input is just past the last token
+            let last = tokens.last().map(|last| last.span).unwrap_or(Span { start: 0, end: 0, line: 1, column: 1 });
+            let span = Span { start: last.end, end: last.end, line: last.line, column: last.column + last.end - last.start };
+            tokens.push(SpannedToken { token: Token::EOI, span });
+        }
+        TokenStream { tokens, pos: 0 }
+    }
+}
+
+impl From<Vec<SpannedToken>> for TokenStream {
+    fn from(tokens: Vec<SpannedToken>) -> TokenStream {
+        TokenStream::new(tokens)
+    }
+}
+
+impl TokenSource for TokenStream {
+    fn current(&self) -> Token {
+        let last = self.tokens.len() - 1;
+        self.tokens[self.pos.min(last)].token.clone()
+    }
+
+    fn peek_next(&self) -> Token {
+        let last = self.tokens.len() - 1;
+        self.tokens[(self.pos + 1).min(last)].token.clone()
+    }
+
+    fn bump(&mut self) {
+        if self.pos < self.tokens.len() - 1 {
+            self.pos += 1;
+        }
+    }
+
+    fn position(&self) -> usize {
+        self.pos
+    }
+
+    fn curr_span(&self) -> Span {
+        self.tokens[self.pos].span
+    }
+
+    fn prev_span(&self) -> Span {
+        match self.pos {
+            0 => Span::default(),
+            pos => self.tokens[pos - 1].span,
+        }
+    }
+}
+
+
+// A hand-written sequence of tokens without source text, for test cases.
+// Every token gets a one column wide span on line 1, in order.
+#[cfg(test)]
+pub struct ScriptedSource {
+    stream: TokenStream,
+}
+
+#[cfg(test)]
+impl ScriptedSource {
+    pub fn new(tokens: Vec<Token>) -> ScriptedSource {
+        let tokens = tokens.into_iter().enumerate()
+            .map(|(i, token)| SpannedToken { token, span: Span { start: i, end: i + 1, line: 1, column: i + 1 } })
+            .collect();
+        ScriptedSource { stream: TokenStream::new(tokens) }
+    }
+}
+
+#[cfg(test)]
+impl TokenSource for ScriptedSource {
+    fn current(&self) -> Token {
+        self.stream.current()
+    }
+
+    fn peek_next(&self) -> Token {
+        self.stream.peek_next()
+    }
+
+    fn bump(&mut self) {
+        self.stream.bump();
+    }
+
+    fn position(&self) -> usize {
+        self.stream.position()
+    }
+
+    fn curr_span(&self) -> Span {
+        self.stream.curr_span()
+    }
+
+    fn prev_span(&self) -> Span {
+        self.stream.prev_span()
+    }
+}
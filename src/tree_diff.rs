@@ -0,0 +1,417 @@
+// Structural diff of two programs, after GumTree (Falleri et al., "Fine-grained
+// and accurate source code differencing", 2014). The trees are compared, not
+// the text, so layout and comments make no difference.
+//
+// 1. Top down: identical subtrees of height 2 or more are matched, largest
+//    first. When a subtree occurs more than once, the copy whose parent looks
+//    the same is preferred.
+// 2. Bottom up: an unmatched node is matched to the node of the same type in
+//    the new tree that holds most of its matched descendants, if they share at
+//    least half of them (or both are the root). Their remaining children are
+//    then matched by type and token, and failing that by type alone.
+// 3. The edits follow from the matching: unmatched old nodes are deleted,
+//    unmatched new nodes are inserted, matched nodes with a different token are
+//    updated, and matched nodes under a different parent, or out of order
+//    among their siblings, are moved.
+//
+// Inserts and deletes are reported for whole subtrees, a node inside an
+// inserted or deleted subtree has no edit of its own unless it moved.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+use crate::parse_tree::NodeType;
+use crate::syntax_tree::{NodeId, SyntaxTree};
+use crate::token::Span;
+
+const MIN_HEIGHT: usize = 2;
+const MIN_DICE: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EditKind {
+    Insert,
+    Delete,
+    Update,
+    Move,
+}
+
+// One edit, with the node in the old and/or the new tree. The labels are the
+// token texts, e.g. the name of an identifier or the value of a literal.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Edit {
+    pub kind: EditKind,
+    pub node_type: NodeType,
+    pub old: Option<NodeId>,  // None for an insert
+    pub new: Option<NodeId>,  // None for a delete
+    pub old_span: Option<Span>,
+    pub new_span: Option<Span>,
+    pub old_label: Option<String>,
+    pub new_label: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TreeDiff {
+    pub matches: Vec<(NodeId, NodeId)>,  // old and new node, in old preorder
+    pub edits: Vec<Edit>,                // deletes in old preorder, then the rest in new preorder
+}
+
+
+pub fn diff(old: &SyntaxTree, new: &SyntaxTree) -> TreeDiff {
+    let mut matcher = Matcher {
+        old: Shape::new(old),
+        new: Shape::new(new),
+        old_to_new: vec![None; old.len()],
+        new_to_old: vec![None; new.len()],
+    };
+    matcher.top_down();
+    matcher.bottom_up();
+    let edits = matcher.edits();
+    let matches = old.ids().filter_map(|id| Some((id, matcher.old_to_new[id.index()]?))).collect();
+    TreeDiff { matches, edits }
+}
+
+
+// A tree with the height, size and structural hash of every subtree
+struct Shape<'t> {
+    tree: &'t SyntaxTree,
+    height: Vec<usize>,
+    size: Vec<usize>,
+    hash: Vec<u64>,
+}
+
+impl<'t> Shape<'t> {
+    fn new(tree: &'t SyntaxTree) -> Shape<'t> {
+        let mut height = vec![1; tree.len()];
+        let mut size = vec![1; tree.len()];
+        let mut hash = vec![0; tree.len()];
+        // children have larger ids, so going backwards sees them first
+        for id in tree.ids().collect::<Vec<_>>().into_iter().rev() {
+            let mut hasher = DefaultHasher::new();
+            (tree.node_type(id) as u8).hash(&mut hasher);
+            label(tree, id).hash(&mut hasher);
+            for child in tree.children(id) {
+                height[id.index()] = height[id.index()].max(height[child.index()] + 1);
+                size[id.index()] += size[child.index()];
+                hash[child.index()].hash(&mut hasher);
+            }
+            hash[id.index()] = hasher.finish();
+        }
+        Shape { tree, height, size, hash }
+    }
+
+    // whether `node` is `root` or below it, ids of a subtree are consecutive
+    fn contains(&self, root: NodeId, node: NodeId) -> bool {
+        root <= node && node.index() < root.index() + self.size[root.index()]
+    }
+}
+
+// what an update changes, the token with its value
+fn label(tree: &SyntaxTree, id: NodeId) -> String {
+    format!("{:?}", tree.token(id))
+}
+
+
+struct Matcher<'t> {
+    old: Shape<'t>,
+    new: Shape<'t>,
+    old_to_new: Vec<Option<NodeId>>,
+    new_to_old: Vec<Option<NodeId>>,
+}
+
+impl Matcher<'_> {
+    fn link(&mut self, old: NodeId, new: NodeId) {
+        self.old_to_new[old.index()] = Some(new);
+        self.new_to_old[new.index()] = Some(old);
+    }
+
+    fn same_node(&self, old: NodeId, new: NodeId) -> bool {
+        self.old.tree.node_type(old) == self.new.tree.node_type(new)
+            && label(self.old.tree, old) == label(self.new.tree, new)
+    }
+
+    // the subtrees are equal, not just their hashes
+    fn isomorphic(&self, old: NodeId, new: NodeId) -> bool {
+        let (old_tree, new_tree) = (self.old.tree, self.new.tree);
+        let mut pending = vec![(old, new)];
+        while let Some((old, new)) = pending.pop() {
+            if !self.same_node(old, new) || old_tree.children(old).len() != new_tree.children(new).len() {
+                return false;
+            }
+            pending.extend(old_tree.children(old).iter().copied().zip(new_tree.children(new).iter().copied()));
+        }
+        true
+    }
+
+    fn link_subtrees(&mut self, old: NodeId, new: NodeId) {
+        let (old_tree, new_tree) = (self.old.tree, self.new.tree);
+        for (old, new) in old_tree.descendants(old).zip(new_tree.descendants(new)) {
+            self.link(old, new);
+        }
+    }
+
+    fn top_down(&mut self) {
+        let mut candidates: HashMap<u64, Vec<NodeId>> = HashMap::new();
+        for id in self.new.tree.ids().filter(|id| self.new.height[id.index()] >= MIN_HEIGHT) {
+            candidates.entry(self.new.hash[id.index()]).or_default().push(id);
+        }
+        let mut subtrees: Vec<NodeId> = self.old.tree.ids().filter(|id| self.old.height[id.index()] >= MIN_HEIGHT).collect();
+        subtrees.sort_by_key(|id| std::cmp::Reverse(self.old.height[id.index()]));
+        for old in subtrees {
+            if self.old_to_new[old.index()].is_some() {
+                continue;
+            }
+            let Some(found) = candidates.get(&self.old.hash[old.index()]) else {
+                continue;
+            };
+            let available: Vec<NodeId> = found.iter().copied()
+                .filter(|&new| self.new_to_old[new.index()].is_none() && self.isomorphic(old, new))
+                .collect();
+            let same_parent = |new: &&NodeId| match (self.old.tree.parent(old), self.new.tree.parent(**new)) {
+                (Some(old_parent), Some(new_parent)) => self.same_node(old_parent, new_parent),
+                _ => false,
+            };
+            if let Some(&new) = available.iter().find(same_parent).or(available.first()) {
+                self.link_subtrees(old, new);
+            }
+        }
+    }
+
+    fn bottom_up(&mut self) {
+        let (old_tree, new_tree) = (self.old.tree, self.new.tree);
+        for old in old_tree.ids().collect::<Vec<_>>().into_iter().rev() {
+            if self.old_to_new[old.index()].is_some() || old_tree.children(old).is_empty() {
+                continue;
+            }
+            // the new nodes holding a partner of one of the descendants
+            let mut seen = HashSet::new();
+            let mut best: Option<(f64, NodeId)> = None;
+            for descendant in old_tree.descendants(old).skip(1) {
+                let Some(partner) = self.old_to_new[descendant.index()] else {
+                    continue;
+                };
+                for candidate in new_tree.ancestors(partner) {
+                    if !seen.insert(candidate) || self.new_to_old[candidate.index()].is_some()
+                        || new_tree.node_type(candidate) != old_tree.node_type(old) {
+                        continue;
+                    }
+                    let dice = self.dice(old, candidate);
+                    if best.is_none_or(|(most, _)| dice > most) {
+                        best = Some((dice, candidate));
+                    }
+                }
+            }
+            if let Some((dice, new)) = best {
+                if dice >= MIN_DICE {
+                    self.link(old, new);
+                    self.recover(old, new);
+                }
+            }
+        }
+        let (old_root, new_root) = (old_tree.root(), new_tree.root());
+        if self.old_to_new[old_root.index()].is_none() && self.new_to_old[new_root.index()].is_none()
+            && old_tree.node_type(old_root) == new_tree.node_type(new_root) {
+            self.link(old_root, new_root);
+            self.recover(old_root, new_root);
+        }
+    }
+
+    // the share of matched descendants the two subtrees have in common
+    fn dice(&self, old: NodeId, new: NodeId) -> f64 {
+        let common = self.old.tree.descendants(old).skip(1)
+            .filter(|descendant| self.old_to_new[descendant.index()].is_some_and(|partner| partner != new && self.new.contains(new, partner)))
+            .count();
+        let total = self.old.size[old.index()] + self.new.size[new.index()] - 2;
+        if total == 0 { 0.0 } else { 2.0 * common as f64 / total as f64 }
+    }
+
+    // match the unmatched children of a matched pair, and theirs in turn
+    fn recover(&mut self, old: NodeId, new: NodeId) {
+        let (old_tree, new_tree) = (self.old.tree, self.new.tree);
+        let mut pending = vec![(old, new)];
+        while let Some((old, new)) = pending.pop() {
+            for exact in [true, false] {
+                for &old_child in old_tree.children(old) {
+                    if self.old_to_new[old_child.index()].is_some() {
+                        continue;
+                    }
+                    let partner = new_tree.children(new).iter().copied().find(|&new_child| {
+                        self.new_to_old[new_child.index()].is_none()
+                            && new_tree.node_type(new_child) == old_tree.node_type(old_child)
+                            && (!exact || self.same_node(old_child, new_child))
+                    });
+                    if let Some(new_child) = partner {
+                        self.link(old_child, new_child);
+                        pending.push((old_child, new_child));
+                    }
+                }
+            }
+        }
+    }
+
+    fn edits(&self) -> Vec<Edit> {
+        let (old_tree, new_tree) = (self.old.tree, self.new.tree);
+        let edit = |kind, old: Option<NodeId>, new: Option<NodeId>| Edit {
+            kind,
+            node_type: match (old, new) {
+                (_, Some(new)) => new_tree.node_type(new),
+                (Some(old), None) => old_tree.node_type(old),
+                (None, None) => unreachable!(),
+            },
+            old,
+            new,
+            old_span: old.map(|old| old_tree.span(old)),
+            new_span: new.map(|new| new_tree.span(new)),
+            old_label: old.map(|old| old_tree.token(old).text()),
+            new_label: new.map(|new| new_tree.token(new).text()),
+        };
+        let mut edits = vec![];
+        for old in old_tree.ids() {
+            let parent_kept = old_tree.parent(old).is_none_or(|parent| self.old_to_new[parent.index()].is_some());
+            if self.old_to_new[old.index()].is_none() && parent_kept {
+                edits.push(edit(EditKind::Delete, Some(old), None));
+            }
+        }
+        let moved = self.moved();
+        for new in new_tree.ids() {
+            let parent_kept = new_tree.parent(new).is_none_or(|parent| self.new_to_old[parent.index()].is_some());
+            match self.new_to_old[new.index()] {
+                None if parent_kept => edits.push(edit(EditKind::Insert, None, Some(new))),
+                None => {}
+                Some(old) => {
+                    if label(old_tree, old) != label(new_tree, new) {
+                        edits.push(edit(EditKind::Update, Some(old), Some(new)));
+                    }
+                    if moved.contains(&new) {
+                        edits.push(edit(EditKind::Move, Some(old), Some(new)));
+                    }
+                }
+            }
+        }
+        edits
+    }
+
+    // The matched new nodes whose old partner had another parent, or that
+    // left their order among the siblings that stayed with them
+    fn moved(&self) -> HashSet<NodeId> {
+        let (old_tree, new_tree) = (self.old.tree, self.new.tree);
+        let mut moved = HashSet::new();
+        for new in new_tree.ids() {
+            let Some(old) = self.new_to_old[new.index()] else {
+                continue;
+            };
+            let new_parent = new_tree.parent(new).and_then(|parent| self.new_to_old[parent.index()]);
+            if new_parent != old_tree.parent(old) {
+                moved.insert(new);
+            }
+            let stayed: Vec<NodeId> = new_tree.children(new).iter().copied()
+                .filter(|child| self.new_to_old[child.index()].is_some_and(|partner| old_tree.parent(partner) == Some(old)))
+                .collect();
+            // the old positions of the children that stayed, those off the
+            // longest increasing run of positions were reordered
+            let positions: Vec<usize> = stayed.iter()
+                .map(|child| {
+                    let partner = self.new_to_old[child.index()].unwrap();
+                    old_tree.children(old).iter().position(|&c| c == partner).unwrap()
+                })
+                .collect();
+            let kept = longest_increasing(&positions);
+            for (i, &child) in stayed.iter().enumerate() {
+                if !kept.contains(&i) {
+                    moved.insert(child);
+                }
+            }
+        }
+        moved
+    }
+}
+
+// the indices of a longest strictly increasing subsequence
+fn longest_increasing(values: &[usize]) -> Vec<usize> {
+    let mut length = vec![1; values.len()];
+    let mut previous: Vec<Option<usize>> = vec![None; values.len()];
+    for i in 0..values.len() {
+        for j in 0..i {
+            if values[j] < values[i] && length[j] + 1 > length[i] {
+                length[i] = length[j] + 1;
+                previous[i] = Some(j);
+            }
+        }
+    }
+    let mut indices = vec![];
+    let mut at = (0..values.len()).max_by_key(|&i| (length[i], std::cmp::Reverse(i)));
+    while let Some(i) = at {
+        indices.push(i);
+        at = previous[i];
+    }
+    indices.reverse();
+    indices
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(source: &str) -> SyntaxTree {
+        SyntaxTree::new(&crate::parse(source).unwrap())
+    }
+
+    // each edit as its kind, node type and old and/or new label
+    fn edits(old: &str, new: &str) -> Vec<String> {
+        diff(&tree(old), &tree(new)).edits.iter().map(|edit| {
+            let labels: Vec<&str> = [&edit.old_label, &edit.new_label].into_iter().flatten().map(String::as_str).collect();
+            format!("{:?} {:?} {}", edit.kind, edit.node_type, labels.join(" -> "))
+        }).collect()
+    }
+
+    const PROGRAM: &str = "\
+func main() [
+    let a = 1;
+    print a;
+    while a < 3 [ a = a + 1; ]
+]";
+
+    #[test]
+    fn identical_trees_match_everywhere() {
+        let (old, new) = (tree(PROGRAM), tree(PROGRAM));
+        let diff = diff(&old, &new);
+        assert!(diff.edits.is_empty());
+        assert_eq!(diff.matches.len(), old.len());
+        assert!(diff.matches.iter().all(|&(old, new)| old == new));
+        // layout and comments make no difference
+        assert!(edits(PROGRAM, "func main() [ let a = 1; // one\n print a; while a < 3 [ a = a + 1; ] ]").is_empty());
+    }
+
+    #[test]
+    fn a_changed_token_is_updated() {
+        let new = PROGRAM.replace("let a = 1", "let a = 2");
+        assert_eq!(edits(PROGRAM, &new), ["Update Literal 1 -> 2"]);
+        let diff = diff(&tree(PROGRAM), &tree(&new));
+        let span = diff.edits[0].new_span.unwrap();
+        assert_eq!((span.line, span.column), (2, 13));
+        assert_eq!(diff.edits[0].old_span, diff.edits[0].new_span);
+    }
+
+    #[test]
+    fn a_new_statement_is_inserted_as_a_whole() {
+        let new = PROGRAM.replace("a = a + 1; ]", "a = a + 1; print a; ]");
+        assert_eq!(edits(PROGRAM, &new), ["Insert PrintStatement print"]);
+    }
+
+    #[test]
+    fn a_removed_statement_is_deleted_as_a_whole() {
+        let new = PROGRAM.replace("    print a;\n", "");
+        assert_eq!(edits(PROGRAM, &new), ["Delete PrintStatement print"]);
+    }
+
+    #[test]
+    fn reordered_or_reparented_statements_are_moved() {
+        let reordered = PROGRAM.replace("    print a;\n", "").replace("]\n]", "]\n    print a;\n]");
+        assert_eq!(edits(PROGRAM, &reordered), ["Move PrintStatement print -> print"]);
+        let reparented = PROGRAM.replace("    print a;\n", "").replace("a = a + 1; ]", "a = a + 1; print a; ]");
+        assert_eq!(edits(PROGRAM, &reparented), ["Move PrintStatement print -> print"]);
+    }
+}
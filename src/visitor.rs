@@ -0,0 +1,212 @@
+use crate::parse_tree::{NodeType, ParseTree};
+
+// Traversals over the parse tree, so a pass only overrides the node kinds it
+// cares about:
+//
+//   struct CountCalls(usize);
+//   impl Visitor for CountCalls {
+//       fn visit_function_call(&mut self, tree: &ParseTree) {
+//           self.0 += 1;
+//           self.walk_function_call(tree);  // keep going into the arguments
+//       }
+//   }
+//
+// `visit` dispatches on the node type to `visit_<kind>`, which by default calls
+// `walk_<kind>`, which visits the children in order. `VisitorMut` does the same
+// over a mutable tree, `Fold` consumes a tree and builds a new one.
+macro_rules! traversals {
+    ($($kind:ident => $visit:ident, $walk:ident, $fold:ident;)*) => {
+        pub trait Visitor {
+            fn visit(&mut self, tree: &ParseTree) {
+                match tree.node_type() {
+                    $(NodeType::$kind => self.$visit(tree),)*
+                }
+            }
+
+            $(
+                fn $visit(&mut self, tree: &ParseTree) {
+                    self.$walk(tree);
+                }
+
+                fn $walk(&mut self, tree: &ParseTree) {
+                    self.walk_children(tree);
+                }
+            )*
+
+            fn walk_children(&mut self, tree: &ParseTree) {
+                for child in tree.children() {
+                    self.visit(child);
+                }
+            }
+        }
+
+        pub trait VisitorMut {
+            fn visit(&mut self, tree: &mut ParseTree) {
+                match tree.node_type() {
+                    $(NodeType::$kind => self.$visit(tree),)*
+                }
+            }
+
+            $(
+                fn $visit(&mut self, tree: &mut ParseTree) {
+                    self.$walk(tree);
+                }
+
+                fn $walk(&mut self, tree: &mut ParseTree) {
+                    self.walk_children(tree);
+                }
+            )*
+
+            fn walk_children(&mut self, tree: &mut ParseTree) {
+                for child in tree.children_mut() {
+                    self.visit(child);
+                }
+            }
+        }
+
+        pub trait Fold {
+            fn fold(&mut self, tree: ParseTree) -> ParseTree {
+                match tree.node_type() {
+                    $(NodeType::$kind => self.$fold(tree),)*
+                }
+            }
+
+            $(
+                fn $fold(&mut self, tree: ParseTree) -> ParseTree {
+                    self.fold_children(tree)
+                }
+            )*
+
+            // rebuild `tree` from its folded children
+            fn fold_children(&mut self, mut tree: ParseTree) -> ParseTree {
+                let children = std::mem::take(tree.children_mut());
+                for child in children {
+                    let child = self.fold(*child);
+                    tree.push(child);
+                }
+                tree
+            }
+        }
+    };
+}
+
+traversals! {
+    Program => visit_program, walk_program, fold_program;
+    FunctionDefinition => visit_function_definition, walk_function_definition, fold_function_definition;
+    StructDefinition => visit_struct_definition, walk_struct_definition, fold_struct_definition;
+    Field => visit_field, walk_field, fold_field;
+    ParameterList => visit_parameter_list, walk_parameter_list, fold_parameter_list;
+    Parameter => visit_parameter, walk_parameter, fold_parameter;
+    Type => visit_type, walk_type, fold_type;
+    ArrayType => visit_array_type, walk_array_type, fold_array_type;
+    Block => visit_block, walk_block, fold_block;
+    VariableDeclaration => visit_variable_declaration, walk_variable_declaration, fold_variable_declaration;
+    ConstantDeclaration => visit_constant_declaration, walk_constant_declaration, fold_constant_declaration;
+    AssignmentStatement => visit_assignment_statement, walk_assignment_statement, fold_assignment_statement;
+    IfStatement => visit_if_statement, walk_if_statement, fold_if_statement;
+    ReturnStatement => visit_return_statement, walk_return_statement, fold_return_statement;
+    PrintStatement => visit_print_statement, walk_print_statement, fold_print_statement;
+    WhileStatement => visit_while_statement, walk_while_statement, fold_while_statement;
+    BreakStatement => visit_break_statement, walk_break_statement, fold_break_statement;
+    ContinueStatement => visit_continue_statement, walk_continue_statement, fold_continue_statement;
+    FunctionCall => visit_function_call, walk_function_call, fold_function_call;
+    BinaryExpression => visit_binary_expression, walk_binary_expression, fold_binary_expression;
+    UnaryExpression => visit_unary_expression, walk_unary_expression, fold_unary_expression;
+    Literal => visit_literal, walk_literal, fold_literal;
+    Identifier => visit_identifier, walk_identifier, fold_identifier;
+    ArrayLiteral => visit_array_literal, walk_array_literal, fold_array_literal;
+    IndexExpression => visit_index_expression, walk_index_expression, fold_index_expression;
+    Index => visit_index, walk_index, fold_index;
+    MemberExpression => visit_member_expression, walk_member_expression, fold_member_expression;
+    Member => visit_member, walk_member, fold_member;
+    Error => visit_error, walk_error, fold_error;
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::Token;
+
+    const PROGRAM: &str = "\
+func main() [
+    let x = 1 + 2 * 3;
+    while x < 10 [ x = f(g(x), 4 - 1); ]
+    println x;
+]";
+
+    // the example from the top, the default walk finds the calls inside others
+    struct CountCalls(usize);
+
+    impl Visitor for CountCalls {
+        fn visit_function_call(&mut self, tree: &ParseTree) {
+            self.0 += 1;
+            self.walk_function_call(tree);
+        }
+    }
+
+    #[test]
+    fn a_visitor_overrides_one_kind_and_walks_the_rest() {
+        let program = crate::parse(PROGRAM).unwrap();
+        let mut count = CountCalls(0);
+        count.visit(&program);
+        assert_eq!(count.0, 2);
+
+        // without the walk the arguments are skipped
+        struct OutermostCalls(Vec<String>);
+        impl Visitor for OutermostCalls {
+            fn visit_function_call(&mut self, tree: &ParseTree) {
+                self.0.push(tree.name());
+            }
+        }
+        let mut calls = OutermostCalls(vec![]);
+        calls.visit(&program);
+        assert_eq!(calls.0, ["f"]);
+    }
+
+    #[test]
+    fn a_mutable_visitor_rewrites_in_place() {
+        struct Rename;
+        impl VisitorMut for Rename {
+            fn visit_identifier(&mut self, tree: &mut ParseTree) {
+                if tree.name() == "x" {
+                    *tree.token_mut() = Token::ID("y".to_string());
+                }
+            }
+        }
+        let mut program = crate::parse(PROGRAM).unwrap();
+        Rename.visit(&mut program);
+        // declarations and assignment targets are not identifier nodes
+        assert_eq!(program.to_sexpr(),
+                   "(program (func main () (block (let x _ (+ 1 (* 2 3))) \
+                    (while (< y 10) (block (= x (call f (call g y) (- 4 1))))) (println y))))");
+    }
+
+    #[test]
+    fn a_fold_rebuilds_the_tree() {
+        // folds integer arithmetic on literals, the children first
+        struct Constants;
+        impl Fold for Constants {
+            fn fold_binary_expression(&mut self, tree: ParseTree) -> ParseTree {
+                let tree = self.fold_children(tree);
+                let [left, right] = tree.children() else {
+                    return tree;
+                };
+                let (Token::LIT_INT32(left), Token::LIT_INT32(right)) = (left.token(), right.token()) else {
+                    return tree;
+                };
+                let value = match tree.token() {
+                    Token::ADD => left + right,
+                    Token::SUB => left - right,
+                    Token::MUL => left * right,
+                    _ => return tree,
+                };
+                ParseTree::new(Token::LIT_INT32(value), NodeType::Literal).with_span(tree.span())
+            }
+        }
+        let program = Constants.fold(crate::parse(PROGRAM).unwrap());
+        assert_eq!(program.to_sexpr(),
+                   "(program (func main () (block (let x _ 7) \
+                    (while (< x 10) (block (= x (call f (call g x) 3)))) (println x))))");
+    }
+}